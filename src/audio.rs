@@ -0,0 +1,169 @@
+use std::io::{Read, Seek};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::codec::{self, RawPcm};
+use crate::error::KakaiaError;
+use crate::resample;
+use crate::vad;
+use crate::Configuration;
+
+// The provided model was trained on this specific sample rate.
+pub const SAMPLE_RATE: u32 = 16_000;
+
+// How to reduce multi-channel audio to the single channel DeepSpeech expects.
+#[derive(Debug, Clone, Copy)]
+pub enum Downmix {
+    // Average all channels.
+    Average,
+    // Keep only the selected channel, counting from 0.
+    Channel(u32),
+}
+
+impl FromStr for Downmix {
+    type Err = String;
+
+    fn from_str(downmix: &str) -> Result<Self, Self::Err> {
+        match downmix {
+            "average" => Ok(Downmix::Average),
+            channel => match channel.parse() {
+                Ok(c) => Ok(Downmix::Channel(c)),
+                Err(_) => Err(format!(
+                    "'{}' is neither 'average' nor a channel number",
+                    downmix
+                )),
+            },
+        }
+    }
+}
+
+// The original audio file, before conversion to 16 kHz mono.
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioDescription {
+    pub format: String,
+    pub channels: u32,
+    pub layout: String,
+    pub sample_rate: u32,
+    // Length of the audio in seconds.
+    pub duration: f64,
+    // Length of the audio after trimming silence, if voice activity detection
+    // is enabled; 0 if no speech was detected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speech_duration: Option<f64>,
+}
+
+pub struct Audio {
+    // 16 kHz mono samples.
+    pub samples: Vec<i16>,
+    // Number of samples trimmed from the start.
+    pub offset: usize,
+    pub description: AudioDescription,
+}
+
+// Decode an audio file into 16 kHz mono samples.
+pub fn decode<R: Read + Seek>(audio_file: R, raw: Option<RawPcm>, config: &Configuration) -> Result<Audio, KakaiaError> {
+    let decoded = codec::decode(audio_file, raw)?;
+    let channels = decoded.channels;
+    if channels == 0 {
+        return Err(KakaiaError::Channel("audio file has no channels".to_string()));
+    }
+    if decoded.sample_rate == 0 {
+        return Err(KakaiaError::Decode("audio file has no sample rate".to_string()));
+    }
+    if let Downmix::Channel(channel) = config.downmix {
+        if channel >= channels {
+            return Err(KakaiaError::Channel(format!(
+                "cannot select channel {} of audio file with {} channels",
+                channel, channels
+            )));
+        }
+    }
+
+    let mono = downmix(&decoded.interleaved, channels, config.downmix);
+
+    // Interpolate to the target sample rate
+    let samples = resample::resample(mono, decoded.sample_rate, SAMPLE_RATE, config.interpolation);
+
+    let duration = samples.len() as f64 / SAMPLE_RATE as f64;
+    Ok(Audio {
+        samples,
+        offset: 0,
+        description: AudioDescription {
+            format: decoded.format,
+            channels,
+            layout: layout(channels),
+            sample_rate: decoded.sample_rate,
+            duration,
+            speech_duration: None,
+        },
+    })
+}
+
+impl Audio {
+    // Trim leading and trailing silence, leaving no samples if there is no
+    // speech at all.
+    pub fn trim_silence(&mut self, margin: f64) {
+        match vad::detect(&self.samples, margin) {
+            Some(activity) => {
+                self.samples.truncate(activity.end);
+                self.samples.drain(..activity.start);
+                self.offset = activity.start;
+            }
+            None => self.samples.clear(),
+        }
+        self.description.speech_duration = Some(self.samples.len() as f64 / SAMPLE_RATE as f64);
+    }
+}
+
+// Reduce interleaved samples to a single channel.
+pub fn downmix(interleaved: &[i16], channels: u32, downmix: Downmix) -> Vec<i16> {
+    if channels == 1 {
+        return interleaved.to_vec();
+    }
+    interleaved
+        .chunks(channels as usize)
+        .map(|frame| match downmix {
+            Downmix::Average => {
+                let sum: i32 = frame.iter().map(|s| *s as i32).sum();
+                (sum / frame.len() as i32) as i16
+            }
+            Downmix::Channel(channel) => frame.get(channel as usize).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+fn layout(channels: u32) -> String {
+    match channels {
+        1 => "mono".to_string(),
+        2 => "stereo".to_string(),
+        n => format!("{} channels", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three stereo frames.
+    const STEREO: [i16; 6] = [1, 2, -4, 10, 100, 0];
+
+    #[test]
+    fn downmix_average_of_channels() {
+        assert_eq!(downmix(&STEREO, 2, Downmix::Average), vec![1, 3, 50]);
+        let loud = [i16::MAX, i16::MAX, i16::MIN, i16::MIN];
+        assert_eq!(downmix(&loud, 2, Downmix::Average), vec![i16::MAX, i16::MIN]);
+    }
+
+    #[test]
+    fn downmix_selected_channel() {
+        assert_eq!(downmix(&STEREO, 2, Downmix::Channel(0)), vec![1, -4, 100]);
+        assert_eq!(downmix(&STEREO, 2, Downmix::Channel(1)), vec![2, 10, 0]);
+    }
+
+    #[test]
+    fn downmix_mono_unchanged() {
+        assert_eq!(downmix(&STEREO, 1, Downmix::Channel(0)), STEREO.to_vec());
+        assert_eq!(downmix(&STEREO, 1, Downmix::Average), STEREO.to_vec());
+    }
+}
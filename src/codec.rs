@@ -0,0 +1,292 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use audrey::read::Reader;
+
+use crate::error::KakaiaError;
+use crate::webm;
+
+// Opus is always decoded at 48 kHz, its native sample rate.
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+// The longest Opus packet is 120 milliseconds.
+const OPUS_MAX_FRAME: usize = 5_760;
+
+// Interleaved samples decoded from an audio file, at its own sample rate.
+pub struct Decoded {
+    pub interleaved: Vec<i16>,
+    pub channels: u32,
+    pub sample_rate: u32,
+    pub format: String,
+}
+
+// Sample rate and channels of headerless 16-bit little-endian PCM, which can't
+// be detected and so must be declared by the client.
+#[derive(Debug, Clone, Copy)]
+pub struct RawPcm {
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+// Decode audio, detecting the format from the first bytes of the file unless
+// it is declared to be raw PCM.
+pub fn decode<R: Read + Seek>(mut audio_file: R, raw: Option<RawPcm>) -> Result<Decoded, KakaiaError> {
+    if let Some(pcm) = raw {
+        return decode_pcm(audio_file, pcm);
+    }
+
+    let mut header = [0u8; 64];
+    let mut len = 0;
+    while len < header.len() {
+        match audio_file.read(&mut header[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) => return Err(KakaiaError::Decode(format!("failed to read audio file: {}", e))),
+        }
+    }
+    if let Err(e) = audio_file.seek(SeekFrom::Start(0)) {
+        return Err(KakaiaError::Decode(format!("failed to read audio file: {}", e)));
+    }
+
+    match sniff(&header[..len]) {
+        Container::OggOpus => decode_ogg_opus(audio_file),
+        Container::WebmOpus => decode_webm_opus(audio_file),
+        Container::Mp3 => decode_mp3(audio_file),
+        Container::Audrey => decode_audrey(audio_file),
+    }
+}
+
+// Decoders picked from the first bytes of a file.
+#[derive(Debug, PartialEq)]
+enum Container {
+    OggOpus,
+    WebmOpus,
+    Mp3,
+    // FLAC, Ogg Vorbis, WAV and ALAC within CAF.
+    Audrey,
+}
+
+fn sniff(header: &[u8]) -> Container {
+    if header.starts_with(b"OggS") && contains(header, b"OpusHead") {
+        Container::OggOpus
+    } else if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Container::WebmOpus
+    } else if is_mp3(header) {
+        Container::Mp3
+    } else {
+        Container::Audrey
+    }
+}
+
+fn decode_audrey<R: Read + Seek>(audio_file: R) -> Result<Decoded, KakaiaError> {
+    let mut reader = match Reader::new(audio_file) {
+        Ok(r) => r,
+        // Audrey can't tell an unknown format from a corrupt header.
+        Err(e) => return Err(KakaiaError::Format(format!("failed to load audio file: {}", e))),
+    };
+
+    let desc = reader.description();
+    let mut interleaved = Vec::new();
+    for sample in reader.samples::<i16>() {
+        match sample {
+            Ok(s) => interleaved.push(s),
+            Err(e) => return Err(KakaiaError::Decode(format!("failed to read audio samples: {}", e))),
+        }
+    }
+
+    let extension = match desc.format() {
+        audrey::Format::Flac => "flac".to_string(),
+        audrey::Format::OggVorbis => "ogg".to_string(),
+        audrey::Format::Wav => "wav".to_string(),
+        audrey::Format::CafAlac => "caf".to_string(),
+    };
+
+    Ok(Decoded {
+        interleaved,
+        channels: desc.channel_count(),
+        sample_rate: desc.sample_rate(),
+        format: extension,
+    })
+}
+
+fn decode_pcm<R: Read>(mut audio_file: R, pcm: RawPcm) -> Result<Decoded, KakaiaError> {
+    let mut bytes = Vec::new();
+    if let Err(e) = audio_file.read_to_end(&mut bytes) {
+        return Err(KakaiaError::Decode(format!("failed to read audio file: {}", e)));
+    }
+    Ok(Decoded {
+        interleaved: bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect(),
+        channels: pcm.channels,
+        sample_rate: pcm.sample_rate,
+        format: "pcm".to_string(),
+    })
+}
+
+// An ID3 tag, or the sync word of an MPEG audio frame (excluding AAC, which
+// has a layer of 0).
+fn is_mp3(header: &[u8]) -> bool {
+    header.starts_with(b"ID3")
+        || (header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0 && header[1] & 0x06 != 0)
+}
+
+fn decode_mp3<R: Read>(audio_file: R) -> Result<Decoded, KakaiaError> {
+    let mut decoder = minimp3::Decoder::new(audio_file);
+    let mut interleaved = Vec::new();
+    let mut format: Option<(u32, u32)> = None;
+    loop {
+        let frame = match decoder.next_frame() {
+            Ok(f) => f,
+            Err(minimp3::Error::Eof) => break,
+            // Tags and garbage between frames are skipped.
+            Err(minimp3::Error::SkippedData) => continue,
+            Err(e) => return Err(KakaiaError::Decode(format!("failed to decode mp3: {:?}", e))),
+        };
+        let frame_format = (frame.channels as u32, frame.sample_rate as u32);
+        match format {
+            None => format = Some(frame_format),
+            Some(f) if f != frame_format => {
+                return Err(KakaiaError::Format(
+                    "mp3 files changing channels or sample rate are not supported".to_string(),
+                ));
+            }
+            _ => (),
+        }
+        interleaved.extend_from_slice(&frame.data);
+    }
+
+    match format {
+        Some((channels, sample_rate)) => Ok(Decoded {
+            interleaved,
+            channels,
+            sample_rate,
+            format: "mp3".to_string(),
+        }),
+        None => Err(KakaiaError::Decode("mp3 file has no audio frames".to_string())),
+    }
+}
+
+fn decode_ogg_opus<R: Read + Seek>(audio_file: R) -> Result<Decoded, KakaiaError> {
+    let mut reader = ogg::PacketReader::new(audio_file);
+    let mut packets = Vec::new();
+    loop {
+        match reader.read_packet() {
+            Ok(Some(p)) => packets.push(p.data),
+            Ok(None) => break,
+            Err(e) => return Err(KakaiaError::Decode(format!("failed to read ogg packet: {}", e))),
+        }
+    }
+    // The identification header is followed by the comment header, and then
+    // the audio packets.
+    if packets.len() < 2 || !packets[0].starts_with(b"OpusHead") || packets[0].len() < 12 {
+        return Err(KakaiaError::Decode("invalid Opus headers".to_string()));
+    }
+    let channels = packets[0][9] as u32;
+    let pre_skip = u16::from_le_bytes([packets[0][10], packets[0][11]]) as usize;
+    decode_opus(packets.split_off(2), channels, pre_skip, "opus")
+}
+
+fn decode_webm_opus<R: Read>(mut audio_file: R) -> Result<Decoded, KakaiaError> {
+    let mut data = Vec::new();
+    if let Err(e) = audio_file.read_to_end(&mut data) {
+        return Err(KakaiaError::Decode(format!("failed to read audio file: {}", e)));
+    }
+    let track = webm::demux_opus(&data).map_err(KakaiaError::Decode)?;
+    decode_opus(track.packets, track.channels, track.pre_skip, "webm")
+}
+
+fn decode_opus(packets: Vec<Vec<u8>>, channels: u32, pre_skip: usize, format: &str) -> Result<Decoded, KakaiaError> {
+    let opus_channels = match channels {
+        1 => opus::Channels::Mono,
+        2 => opus::Channels::Stereo,
+        _ => {
+            return Err(KakaiaError::Channel(format!(
+                "Opus audio with {} channels is not supported",
+                channels
+            )))
+        }
+    };
+    let mut decoder = match opus::Decoder::new(OPUS_SAMPLE_RATE, opus_channels) {
+        Ok(d) => d,
+        // The channels were checked above and the rate is fixed, so this isn't the upload's fault.
+        Err(e) => return Err(KakaiaError::Internal(format!("failed to create Opus decoder: {}", e))),
+    };
+
+    let mut buffer = vec![0i16; OPUS_MAX_FRAME * channels as usize];
+    let mut interleaved = Vec::new();
+    for packet in packets {
+        let samples = match decoder.decode(&packet, &mut buffer, false) {
+            Ok(s) => s,
+            Err(e) => return Err(KakaiaError::Decode(format!("failed to decode Opus packet: {}", e))),
+        };
+        interleaved.extend_from_slice(&buffer[..samples * channels as usize]);
+    }
+    // Discard the encoder's priming samples.
+    let skip = (pre_skip * channels as usize).min(interleaved.len());
+    interleaved.drain(..skip);
+
+    Ok(Decoded {
+        interleaved,
+        channels,
+        sample_rate: OPUS_SAMPLE_RATE,
+        format: format.to_string(),
+    })
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn sniff_ogg() {
+        let mut opus = b"OggS\x00\x02".to_vec();
+        opus.extend_from_slice(&[0; 22]);
+        opus.extend_from_slice(b"\x13OpusHead\x01\x01");
+        assert_eq!(sniff(&opus), Container::OggOpus);
+        // Ogg Vorbis is left to audrey.
+        assert_eq!(sniff(b"OggS\x00\x02\x00\x00\x01vorbis"), Container::Audrey);
+    }
+
+    #[test]
+    fn sniff_webm() {
+        assert_eq!(sniff(&[0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x86, 0x81, 0x01]), Container::WebmOpus);
+        assert_eq!(sniff(&[0x1A, 0x45, 0xDF]), Container::Audrey);
+    }
+
+    #[test]
+    fn sniff_mp3() {
+        assert_eq!(sniff(b"ID3\x04\x00\x00"), Container::Mp3);
+        // MPEG-1 layer III.
+        assert_eq!(sniff(&[0xFF, 0xFB, 0x90, 0x64]), Container::Mp3);
+        // ADTS AAC has a layer of 0.
+        assert_eq!(sniff(&[0xFF, 0xF1, 0x50, 0x80]), Container::Audrey);
+        assert_eq!(sniff(&[0xFF]), Container::Audrey);
+    }
+
+    #[test]
+    fn sniff_others() {
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00WAVEfmt "), Container::Audrey);
+        assert_eq!(sniff(b"fLaC\x00\x00\x00\x22"), Container::Audrey);
+        assert_eq!(sniff(&[]), Container::Audrey);
+    }
+
+    #[test]
+    fn decode_declared_pcm() {
+        // Raw PCM is never sniffed, even when it starts like another format.
+        let bytes = [0x49, 0x44, 0x33, 0x00, 0xFF, 0x7F, 0x01];
+        let pcm = RawPcm {
+            sample_rate: 8000,
+            channels: 1,
+        };
+        let decoded = decode(Cursor::new(&bytes[..]), Some(pcm)).unwrap();
+        // The odd trailing byte is dropped.
+        assert_eq!(decoded.interleaved, vec![0x4449, 0x0033, 0x7FFF]);
+        assert_eq!(decoded.sample_rate, 8000);
+        assert_eq!(decoded.format, "pcm");
+    }
+}
@@ -0,0 +1,209 @@
+use serde::Serialize;
+
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::slot::Slots;
+
+// Decimal places of the converted temperature in the human response.
+const HUMAN_DECIMALS: i32 = 2;
+
+// A temperature scale, with the size of its degree in kelvins and absolute
+// zero in its degrees.
+struct Scale {
+    name: &'static str,
+    degree: f64,
+    zero: f64,
+}
+
+// The names are the values of the temperature_name entity of the NLU dataset.
+static SCALES: [Scale; 4] = [
+    Scale {
+        name: "celsius",
+        degree: 1.0,
+        zero: -273.15,
+    },
+    Scale {
+        name: "fahrenheit",
+        degree: 5.0 / 9.0,
+        zero: -459.67,
+    },
+    Scale {
+        name: "kelvin",
+        degree: 1.0,
+        zero: 0.0,
+    },
+    Scale {
+        name: "rankine",
+        degree: 5.0 / 9.0,
+        zero: 0.0,
+    },
+];
+
+// convertTemperature command, return converted temperature, or converted
+// difference of temperatures for "a rise of 10 degrees celsius in fahrenheit"
+pub struct ConvertTemperature;
+
+impl CommandHandler for ConvertTemperature {
+    fn intent(&self) -> &'static str {
+        "convertTemperature"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("snips/temperature", "from"), ("temperature_name", "to")]
+    }
+
+    // The engine recognizes celsius, fahrenheit and kelvin as the unit of the
+    // temperature, other scales such as rankine are a separate from_scale.
+    fn optional_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("temperature_name", "from_scale"), ("temperature_change", "change")]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let from = slots.temperature("snips/temperature", "from")?;
+        let from_degrees = from.value;
+        let from_scale = if slots.has("temperature_name", "from_scale") {
+            slots.custom("temperature_name", "from_scale")?.to_string()
+        } else {
+            match from.unit.as_deref() {
+                // Plain "degrees" could be any scale.
+                None | Some("degree") => {
+                    return Err(NotUnderstood("slot 'from' has no scale, such as celsius".to_string()))
+                }
+                Some(u) => u.to_string(),
+            }
+        };
+        let to_scale = slots.custom("temperature_name", "to")?.to_string();
+        let (from, to) = match (scale(&from_scale), scale(&to_scale)) {
+            (Some(from), Some(to)) => (from, to),
+            // The engine understood the request, it just can't be answered.
+            _ => {
+                let human = format!("converting from {} to {} isn't supported", from_scale, to_scale);
+                return Ok(KakaiaResponse::new(self.intent(), &human, raw, 0.0));
+            }
+        };
+        let change = if slots.has("temperature_change", "change") {
+            Some(slots.custom("temperature_change", "change")?.to_string())
+        } else {
+            None
+        };
+        let result = convert(from_degrees, from, to, change.is_some())?;
+
+        let rounded = round(result);
+        let human = match &change {
+            Some(change) => format!(
+                "a {} of {} degrees {} is a {} of {} degrees {}",
+                change, from_degrees, from.name, change, rounded, to.name
+            ),
+            None => format!("{} degrees {} is {} degrees {}", from_degrees, from.name, rounded, to.name),
+        };
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, result);
+        kakaia_response.data = Some(CommandData::new(&ConvertTemperatureData {
+            value: result,
+            from_value: from_degrees,
+            from_unit: from.name.to_string(),
+            to_unit: to.name.to_string(),
+            delta: change.is_some(),
+        }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, _context: &Context) {
+    registry.register(Box::new(ConvertTemperature));
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConvertTemperatureData {
+    // The converted temperature.
+    value: f64,
+    from_value: f64,
+    from_unit: String,
+    to_unit: String,
+    // True if a difference of temperatures was converted, such as a rise.
+    delta: bool,
+}
+
+fn scale(name: &str) -> Option<&'static Scale> {
+    SCALES.iter().find(|s| s.name == name)
+}
+
+// Convert a temperature between scales, or a difference of temperatures if
+// delta is set.
+fn convert(degrees: f64, from: &Scale, to: &Scale, delta: bool) -> Result<f64, NotUnderstood> {
+    if !delta && degrees < from.zero {
+        return Err(NotUnderstood(format!(
+            "{} degrees {} is below absolute zero",
+            degrees, from.name
+        )));
+    }
+    Ok(if from.name == to.name {
+        degrees
+    } else if delta {
+        // A difference of temperatures only depends on the size of a degree.
+        degrees * from.degree / to.degree
+    } else {
+        (degrees - from.zero) * from.degree / to.degree + to.zero
+    })
+}
+
+// Round for the human response, without answering "-0 degrees".
+fn round(degrees: f64) -> f64 {
+    let scale = 10f64.powi(HUMAN_DECIMALS);
+    (degrees * scale).round() / scale + 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert_to(degrees: f64, from: &str, to: &str, delta: bool) -> f64 {
+        convert(degrees, scale(from).unwrap(), scale(to).unwrap(), delta).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn absolute() {
+        assert_close(convert_to(100.0, "celsius", "fahrenheit", false), 212.0);
+        assert_close(convert_to(32.0, "fahrenheit", "celsius", false), 0.0);
+        assert_close(convert_to(0.0, "celsius", "kelvin", false), 273.15);
+        assert_close(convert_to(-40.0, "fahrenheit", "celsius", false), -40.0);
+    }
+
+    #[test]
+    fn rankine_round_trip() {
+        assert_close(convert_to(0.0, "fahrenheit", "rankine", false), 459.67);
+        assert_close(convert_to(100.0, "kelvin", "rankine", false), 180.0);
+        for &(degrees, scale) in &[(25.0, "celsius"), (-10.5, "fahrenheit"), (300.0, "kelvin")] {
+            let rankine = convert_to(degrees, scale, "rankine", false);
+            assert_close(convert_to(rankine, "rankine", scale, false), degrees);
+        }
+    }
+
+    #[test]
+    fn delta_ignores_zero() {
+        assert_close(convert_to(10.0, "celsius", "fahrenheit", true), 18.0);
+        assert_close(convert_to(10.0, "celsius", "kelvin", true), 10.0);
+        assert_close(convert_to(9.0, "rankine", "celsius", true), 5.0);
+        // A difference isn't limited by absolute zero.
+        assert_close(convert_to(-500.0, "fahrenheit", "rankine", true), -500.0);
+    }
+
+    #[test]
+    fn below_absolute_zero() {
+        assert!(convert(-273.16, scale("celsius").unwrap(), scale("kelvin").unwrap(), false).is_err());
+        assert!(convert(-1.0, scale("rankine").unwrap(), scale("celsius").unwrap(), false).is_err());
+        assert_close(convert_to(-459.67, "fahrenheit", "kelvin", false), 0.0);
+    }
+
+    #[test]
+    fn human_rounding() {
+        assert_eq!(round(convert_to(1.0, "celsius", "fahrenheit", false)), 33.8);
+        assert_eq!(round(convert_to(50.0, "fahrenheit", "celsius", false)), 10.0);
+        assert_eq!(round(convert_to(1.0, "fahrenheit", "kelvin", false)), 255.93);
+        assert_eq!(round(2.0 / 3.0), 0.67);
+        // No "-0 degrees".
+        assert!(round(-0.001).is_sign_positive());
+    }
+}
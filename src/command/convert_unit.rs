@@ -0,0 +1,78 @@
+use serde::Serialize;
+
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::slot::Slots;
+use crate::unit::{self, Unit};
+
+// Significant digits of the converted value in the human response.
+const HUMAN_DIGITS: i32 = 6;
+
+// convertUnit command, return the amount converted to a unit of the same
+// dimension, such as miles to kilometers
+pub struct ConvertUnit;
+
+impl CommandHandler for ConvertUnit {
+    fn intent(&self) -> &'static str {
+        "convertUnit"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("unit", "from"), ("unit", "to")]
+    }
+
+    // Without an amount, as in "how many feet are in a mile", one is converted.
+    // The amount is a plain snips/number: snips/amountOfMoney resolves to the
+    // same numeric value, but only matches amounts with a currency, such as
+    // "five dollars", so "five miles" would leave it empty.
+    fn optional_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("snips/number", "amount")]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let amount = if slots.has("snips/number", "amount") {
+            slots.number("snips/number", "amount")?
+        } else {
+            1.0
+        };
+        let from = Unit::find(slots.custom("unit", "from")?).map_err(|e| NotUnderstood(e.to_string()))?;
+        let to = Unit::find(slots.custom("unit", "to")?).map_err(|e| NotUnderstood(e.to_string()))?;
+        let result = unit::convert(amount, &from, &to).map_err(|e| NotUnderstood(e.to_string()))?;
+        let rounded = unit::round_significant(result, HUMAN_DIGITS);
+        let mut kakaia_response = KakaiaResponse::new(
+            self.intent(),
+            format!(
+                "{} {} is {} {}",
+                amount,
+                from.name_for(amount),
+                rounded,
+                to.name_for(rounded)
+            )
+            .as_str(),
+            raw,
+            result,
+        );
+        kakaia_response.data = Some(CommandData::new(&ConvertUnitData {
+            value: result,
+            from_value: amount,
+            from_unit: from.name.to_string(),
+            to_unit: to.name.to_string(),
+            dimension: from.dimension.to_string(),
+        }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, _context: &Context) {
+    registry.register(Box::new(ConvertUnit));
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConvertUnitData {
+    // The converted amount.
+    value: f64,
+    from_value: f64,
+    from_unit: String,
+    to_unit: String,
+    // Such as "length" or "data size".
+    dimension: String,
+}
@@ -0,0 +1,63 @@
+use actix_web::web;
+use chrono::Utc;
+use chrono_tz::Tz;
+
+use crate::command::list_alarms::describe;
+use crate::command::set_alarm::resolve_time;
+use crate::command::set_reminder::reminder_subject;
+use crate::command::set_timer::TimerData;
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::scheduler::Scheduler;
+use crate::slot::Slots;
+
+// deleteAlarm command, delete the alarm or reminder due at the spoken time, the
+// reminder of the spoken subject, or the only one if neither is spoken
+pub struct DeleteAlarm {
+    pub timezone: Tz,
+    pub scheduler: web::Data<Scheduler>,
+}
+
+impl CommandHandler for DeleteAlarm {
+    fn intent(&self) -> &'static str {
+        "deleteAlarm"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    fn optional_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("snips/datetime", "time"), ("reminder_subject", "subject")]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        // "tomorrow" matches anything due tomorrow, "7am" anything due between
+        // 7 and 8.
+        let window = if slots.has("snips/datetime", "time") {
+            let time = slots.instant_time("snips/datetime", "time")?;
+            let from = resolve_time(time, &self.timezone)?;
+            let span = match time.grain.span() {
+                Some(s) => s,
+                None => return Err(NotUnderstood("slot 'time' is too vague".to_string())),
+            };
+            Some((from.timestamp_millis(), (from + span).timestamp_millis()))
+        } else {
+            None
+        };
+        let deleted = self
+            .scheduler
+            .delete(reminder_subject(slots)?, window, Utc::now())
+            .map_err(NotUnderstood)?;
+        let human = format!("deleted {}", describe(&deleted));
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, deleted.remaining);
+        kakaia_response.data = Some(CommandData::new(&TimerData { timer: deleted }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, context: &Context) {
+    registry.register(Box::new(DeleteAlarm {
+        timezone: context.config.timezone,
+        scheduler: context.scheduler.clone(),
+    }));
+}
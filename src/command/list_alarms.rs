@@ -0,0 +1,61 @@
+use actix_web::web;
+use chrono::{DateTime, Utc};
+
+use crate::command::list_timers::TimersData;
+use crate::command::set_alarm::human_time;
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::scheduler::{Kind, Scheduler, TimerStatus};
+use crate::slot::Slots;
+
+// listAlarms command, return every alarm and reminder, the next one due first
+pub struct ListAlarms {
+    pub scheduler: web::Data<Scheduler>,
+}
+
+impl CommandHandler for ListAlarms {
+    fn intent(&self) -> &'static str {
+        "listAlarms"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    fn execute(&self, _slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let alarms: Vec<TimerStatus> = self
+            .scheduler
+            .list(Utc::now())
+            .into_iter()
+            .filter(|t| t.kind != Kind::Timer)
+            .collect();
+        let descriptions: Vec<String> = alarms.iter().map(describe).collect();
+        let human = match alarms.len() {
+            0 => "you have no alarms or reminders".to_string(),
+            1 => format!("you have {}", descriptions[0]),
+            count => format!("you have {} alarms and reminders: {}", count, descriptions.join(", ")),
+        };
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, alarms.len() as f64);
+        kakaia_response.data = Some(CommandData::new(&TimersData { timers: alarms }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, context: &Context) {
+    registry.register(Box::new(ListAlarms {
+        scheduler: context.scheduler.clone(),
+    }));
+}
+
+// An alarm or reminder as spoken back, such as "a reminder to call mom at
+// 17:00 on Sunday 15 March".
+pub fn describe(alarm: &TimerStatus) -> String {
+    let at = match alarm.end.as_ref().and_then(|e| DateTime::parse_from_rfc3339(e).ok()) {
+        Some(end) => format!(" at {}", human_time(&end)),
+        None => String::new(),
+    };
+    match (alarm.kind, &alarm.name) {
+        (Kind::Reminder, Some(subject)) => format!("a reminder to {}{}", subject, at),
+        (Kind::Reminder, None) => format!("a reminder{}", at),
+        _ => format!("an alarm{}", at),
+    }
+}
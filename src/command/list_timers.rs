@@ -0,0 +1,62 @@
+use actix_web::web;
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::scheduler::{self, Kind, Scheduler, TimerStatus};
+use crate::slot::Slots;
+
+// listTimers command, return every timer and the time it has left
+pub struct ListTimers {
+    pub scheduler: web::Data<Scheduler>,
+}
+
+impl CommandHandler for ListTimers {
+    fn intent(&self) -> &'static str {
+        "listTimers"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    fn execute(&self, _slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let timers: Vec<TimerStatus> = self
+            .scheduler
+            .list(Utc::now())
+            .into_iter()
+            .filter(|t| t.kind == Kind::Timer)
+            .collect();
+        let descriptions: Vec<String> = timers
+            .iter()
+            .map(|t| {
+                format!(
+                    "{} with {} left{}",
+                    t.name.as_ref().map_or("a timer".to_string(), |n| format!("a {} timer", n)),
+                    scheduler::human_duration(t.remaining),
+                    if t.paused { ", paused" } else { "" }
+                )
+            })
+            .collect();
+        let human = match timers.len() {
+            0 => "you have no timers".to_string(),
+            1 => format!("you have {}", descriptions[0]),
+            count => format!("you have {} timers: {}", count, descriptions.join(", ")),
+        };
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, timers.len() as f64);
+        kakaia_response.data = Some(CommandData::new(&TimersData { timers }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, context: &Context) {
+    registry.register(Box::new(ListTimers {
+        scheduler: context.scheduler.clone(),
+    }));
+}
+
+// The result of listTimers and listAlarms.
+#[derive(Debug, Serialize)]
+pub struct TimersData {
+    pub timers: Vec<TimerStatus>,
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use actix_web::error::BlockingError;
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use snips_nlu_lib::ontology::IntentParserResult;
+
+use crate::audio::AudioDescription;
+use crate::error::{ErrorDescription, KakaiaError};
+use crate::nlu::{self, IntentAlternative, NLU};
+use crate::scheduler::Scheduler;
+use crate::slot::{SlotError, Slots};
+use crate::speech::WordTiming;
+use crate::pool::Pool;
+use crate::Configuration;
+
+// Every command module, each adding its commands to the registry with its
+// own register function.
+macro_rules! commands {
+    ($($module:ident),* $(,)?) => {
+        $(pub mod $module;)*
+
+        const COMMANDS: &[fn(&mut Registry, &Context)] = &[$($module::register),*];
+    };
+}
+
+commands!(
+    convert_temperature,
+    convert_unit,
+    delete_alarm,
+    list_alarms,
+    list_timers,
+    set_alarm,
+    set_reminder,
+    set_timer,
+    simple_calculation,
+    timer_action,
+);
+
+#[derive(Debug, Serialize)]
+pub struct KakaiaResponse {
+    command: String,
+    human: String,
+    raw: String,
+    result: f64,
+    // Typed result of the command, replacing result and human for new clients.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<CommandData>,
+    // The uploaded audio, when the command was spoken.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioDescription>,
+    // When each recognized word was spoken.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<WordTiming>,
+    // Overall confidence of the speech recognition, as reported by DeepSpeech.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    // Probability of the recognized intent, as estimated by the NLU engine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probability: Option<f64>,
+    // Other intents the text may have meant, if requested with --alternatives.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<IntentAlternative>,
+    // Why the command was not understood, if it wasn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    // Why the request failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDescription>,
+}
+
+// Bumped whenever the shape of CommandData changes incompatibly.
+pub const DATA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct CommandData {
+    pub version: u32,
+    // The fields of the command's own result type, identified by the command
+    // field of the response.
+    #[serde(flatten)]
+    pub payload: Map<String, Value>,
+}
+
+impl CommandData {
+    // Commands define their result as any type serializing to a JSON object.
+    pub fn new<T: Serialize>(payload: &T) -> Self {
+        let payload = match serde_json::to_value(payload) {
+            Ok(Value::Object(fields)) => fields,
+            Ok(other) => {
+                eprintln!("Unexpected command data, not an object: {}", other);
+                Map::new()
+            }
+            Err(e) => {
+                eprintln!("Unexpected error serializing command data: {}", e);
+                Map::new()
+            }
+        };
+        CommandData {
+            version: DATA_VERSION,
+            payload,
+        }
+    }
+}
+
+// A command run when the NLU engine recognizes its intent. Commands live in
+// their own modules, listed in commands!, which add them to the registry.
+pub trait CommandHandler: Send + Sync {
+    // Name of the intent in the NLU engine.
+    fn intent(&self) -> &'static str;
+
+    // Slots the command reads, as (entity, slot name) pairs, all of which must
+    // have been recognized for the command to run.
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)];
+
+    // Slots the command reads if they were recognized, such as a name.
+    fn optional_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    // Run the command, or explain why the text couldn't be acted on.
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood>;
+}
+
+// Why a command couldn't make sense of the text, answered as "not understood".
+#[derive(Debug)]
+pub struct NotUnderstood(pub String);
+
+impl From<SlotError> for NotUnderstood {
+    fn from(error: SlotError) -> Self {
+        NotUnderstood(error.to_string())
+    }
+}
+
+// All commands, by intent name.
+pub struct Registry {
+    handlers: HashMap<&'static str, Box<dyn CommandHandler>>,
+}
+
+// What commands may need from the server when they are registered.
+pub struct Context<'a> {
+    pub config: &'a Configuration,
+    pub scheduler: &'a web::Data<Scheduler>,
+}
+
+impl Registry {
+    pub fn new(config: &Configuration, scheduler: &web::Data<Scheduler>) -> Self {
+        let mut registry = Registry {
+            handlers: HashMap::new(),
+        };
+        let context = Context { config, scheduler };
+        for register in COMMANDS {
+            register(&mut registry, &context);
+        }
+        registry
+    }
+
+    pub fn register(&mut self, handler: Box<dyn CommandHandler>) {
+        let intent = handler.intent();
+        if self.handlers.insert(intent, handler).is_some() {
+            eprintln!("FATAL ERROR, more than one command handles intent '{}'", intent);
+            std::process::exit(1);
+        }
+    }
+
+    pub fn get(&self, intent: &str) -> Option<&dyn CommandHandler> {
+        self.handlers.get(intent).map(|h| h.as_ref())
+    }
+
+    // Compare the intents and slots of the trained NLU engine with the
+    // registered commands, returning a description of each mismatch. Slots
+    // the engine recognizes but no command reads are only reported as notes,
+    // as they are harmless.
+    pub fn check(&self, engine: &Path) -> Result<Vec<String>, String> {
+        let mappings = nlu::slot_mappings(engine)?;
+        let mut mismatches = Vec::new();
+
+        for (intent, slots) in &mappings {
+            let handler = match self.get(intent) {
+                Some(h) => h,
+                None => {
+                    mismatches.push(format!(
+                        "intent '{}' has no command, it will always be answered with \"no command\"",
+                        intent
+                    ));
+                    continue;
+                }
+            };
+            for (entity, slot_name) in handler.required_slots() {
+                match slots.get(*slot_name) {
+                    Some(e) if e == *entity => (),
+                    Some(e) => mismatches.push(format!(
+                        "command '{}' reads slot '{}' as {}, but the engine recognizes it as {}",
+                        intent, slot_name, entity, e
+                    )),
+                    None => mismatches.push(format!(
+                        "command '{}' requires slot '{}', which the engine doesn't recognize",
+                        intent, slot_name
+                    )),
+                }
+            }
+            for (entity, slot_name) in handler.optional_slots() {
+                match slots.get(*slot_name) {
+                    Some(e) if e != *entity => mismatches.push(format!(
+                        "command '{}' reads slot '{}' as {}, but the engine recognizes it as {}",
+                        intent, slot_name, entity, e
+                    )),
+                    _ => (),
+                }
+            }
+            for slot_name in slots.keys() {
+                let mut read = handler.required_slots().iter().chain(handler.optional_slots());
+                if !read.any(|(_, s)| s == slot_name) {
+                    eprintln!("NOTE, slot '{}' of intent '{}' isn't read by its command", slot_name, intent);
+                }
+            }
+        }
+
+        let mut unknown: Vec<&str> = self
+            .handlers
+            .keys()
+            .filter(|intent| !mappings.contains_key(**intent))
+            .copied()
+            .collect();
+        unknown.sort();
+        for intent in unknown {
+            mismatches.push(format!("command '{}' handles an intent the engine doesn't have", intent));
+        }
+        Ok(mismatches)
+    }
+}
+
+impl KakaiaResponse {
+    pub fn new(command: &str, human: &str, raw: &str, result: f64) -> Self {
+        KakaiaResponse {
+            command: command.to_string(),
+            human: human.to_string(),
+            raw: raw.to_string(),
+            result: result,
+            data: None,
+            audio: None,
+            words: Vec::new(),
+            confidence: None,
+            probability: None,
+            alternatives: Vec::new(),
+            reason: None,
+            error: None,
+        }
+    }
+
+    pub fn not_understood(raw: &str, reason: &str) -> Self {
+        let mut kakaia_response = KakaiaResponse::new("none", "not understood", raw, 0.0);
+        kakaia_response.reason = Some(reason.to_string());
+        kakaia_response
+    }
+
+    pub fn to_json_string(&self) -> String {
+        match serde_json::to_string(&self) {
+            Ok(s) => s,
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+}
+
+// Parse text with the NLU engine and run the resulting command
+pub fn run_command(registry: &Registry, nlu: &NLU, raw: &str) -> Result<KakaiaResponse, KakaiaError> {
+    let parsed = nlu.parse(raw)?;
+    //println!("NLU: {:?}", &parsed);
+
+    let mut kakaia_response = dispatch(registry, nlu, &parsed, raw);
+    kakaia_response.probability = Some(nlu.get_probability(&parsed));
+    kakaia_response.alternatives = nlu.get_alternatives(&parsed);
+    Ok(kakaia_response)
+}
+
+// Run the command handling the parsed intent
+fn dispatch(registry: &Registry, nlu: &NLU, parsed: &IntentParserResult, raw: &str) -> KakaiaResponse {
+    let command_string = nlu.get_command(parsed);
+    let handler = match registry.get(&command_string) {
+        Some(h) => h,
+        // no command, we do nothing
+        None => return KakaiaResponse::new("none", "no command", raw, 0.0),
+    };
+    // too unlikely to act on
+    let probability = nlu.get_probability(parsed);
+    if probability < nlu.min_probability {
+        let reason = format!(
+            "intent '{}' has a probability of {:.2}, below the minimum of {}",
+            command_string, probability, nlu.min_probability
+        );
+        return KakaiaResponse::not_understood(raw, &reason);
+    }
+    let slots = Slots::new(parsed);
+    for (entity, slot_name) in handler.required_slots() {
+        if let Err(e) = slots.get(entity, slot_name) {
+            return KakaiaResponse::not_understood(raw, &e.to_string());
+        }
+    }
+    match handler.execute(&slots, raw) {
+        Ok(kakaia_response) => kakaia_response,
+        Err(NotUnderstood(reason)) => KakaiaResponse::not_understood(raw, &reason),
+    }
+}
+
+pub async fn _text_to_command(
+    registry: web::Data<Registry>,
+    nlu_pool: web::Data<Pool<NLU>>,
+    text: String,
+) -> HttpResponse {
+    // Parsing blocks, so run it on the thread pool.
+    let nlu_pool = nlu_pool.get_ref().clone();
+    let raw = text.trim().to_string();
+    let parsed = web::block(move || {
+        let nlu = nlu_pool.checkout()?;
+        run_command(&registry, &nlu, text.trim())
+    })
+    .await;
+
+    match parsed {
+        Ok(kakaia_response) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(kakaia_response.to_json_string()),
+        Err(BlockingError::Error(error)) => error.http_response(&raw),
+        Err(BlockingError::Canceled) => KakaiaError::Canceled.http_response(&raw),
+    }
+}
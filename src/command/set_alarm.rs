@@ -0,0 +1,76 @@
+use std::fmt;
+
+use actix_web::web;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::command::set_timer::TimerData;
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::scheduler::{Kind, Scheduler};
+use crate::slot::{Grain, InstantTime, Slots};
+
+// setAlarm command, set an alarm going off at the spoken time in the configured
+// timezone, returning how many seconds until it does
+pub struct SetAlarm {
+    pub timezone: Tz,
+    pub scheduler: web::Data<Scheduler>,
+}
+
+impl CommandHandler for SetAlarm {
+    fn intent(&self) -> &'static str {
+        "setAlarm"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("snips/datetime", "time")]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let now = Utc::now();
+        let due = due_time(slots, &self.timezone, now)?;
+        let alarm = self
+            .scheduler
+            .add(Kind::Alarm, None, now, due.with_timezone(&Utc))
+            .map_err(NotUnderstood)?;
+        let human = format!("set alarm for {}", human_time(&due));
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, alarm.remaining);
+        kakaia_response.data = Some(CommandData::new(&TimerData { timer: alarm }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, context: &Context) {
+    registry.register(Box::new(SetAlarm {
+        timezone: context.config.timezone,
+        scheduler: context.scheduler.clone(),
+    }));
+}
+
+// When an alarm or reminder is due, which must be a time of day in the future.
+pub fn due_time(slots: &Slots, timezone: &Tz, now: DateTime<Utc>) -> Result<DateTime<Tz>, NotUnderstood> {
+    let time = slots.instant_time("snips/datetime", "time")?;
+    match time.grain {
+        Grain::Hour | Grain::Minute | Grain::Second => (),
+        _ => return Err(NotUnderstood("slot 'time' has no time of day".to_string())),
+    }
+    let due = resolve_time(time, timezone)?;
+    if due.with_timezone(&Utc) <= now {
+        return Err(NotUnderstood(format!("{} has already passed", human_time(&due))));
+    }
+    Ok(due)
+}
+
+pub fn resolve_time(time: &InstantTime, timezone: &Tz) -> Result<DateTime<Tz>, NotUnderstood> {
+    match time.resolve(timezone) {
+        Some(t) => Ok(t),
+        None => Err(NotUnderstood(format!("time '{}' can't be read", time.value))),
+    }
+}
+
+// A time as spoken back, such as "07:00 on Sunday 15 March".
+pub fn human_time<T: TimeZone>(time: &DateTime<T>) -> String
+where
+    T::Offset: fmt::Display,
+{
+    time.format("%H:%M on %A %-d %B").to_string()
+}
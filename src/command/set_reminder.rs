@@ -0,0 +1,62 @@
+use actix_web::web;
+use chrono::Utc;
+use chrono_tz::Tz;
+
+use crate::command::set_alarm::{due_time, human_time};
+use crate::command::set_timer::TimerData;
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::scheduler::{Kind, Scheduler};
+use crate::slot::Slots;
+
+// setReminder command, set a reminder of something, due at the spoken time in
+// the configured timezone, returning how many seconds until it is
+pub struct SetReminder {
+    pub timezone: Tz,
+    pub scheduler: web::Data<Scheduler>,
+}
+
+impl CommandHandler for SetReminder {
+    fn intent(&self) -> &'static str {
+        "setReminder"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("snips/datetime", "time")]
+    }
+
+    fn optional_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("reminder_subject", "subject")]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let now = Utc::now();
+        let due = due_time(slots, &self.timezone, now)?;
+        let subject = reminder_subject(slots)?;
+        let reminder = self
+            .scheduler
+            .add(Kind::Reminder, subject, now, due.with_timezone(&Utc))
+            .map_err(NotUnderstood)?;
+        let human = match subject {
+            Some(subject) => format!("set reminder to {} for {}", subject, human_time(&due)),
+            None => format!("set reminder for {}", human_time(&due)),
+        };
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, reminder.remaining);
+        kakaia_response.data = Some(CommandData::new(&TimerData { timer: reminder }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, context: &Context) {
+    registry.register(Box::new(SetReminder {
+        timezone: context.config.timezone,
+        scheduler: context.scheduler.clone(),
+    }));
+}
+
+// What to be reminded of, such as "call mom", if spoken.
+pub fn reminder_subject(slots: &Slots) -> Result<Option<&str>, NotUnderstood> {
+    if !slots.has("reminder_subject", "subject") {
+        return Ok(None);
+    }
+    Ok(Some(slots.custom("reminder_subject", "subject")?))
+}
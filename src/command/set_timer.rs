@@ -0,0 +1,94 @@
+use actix_web::web;
+use chrono::Utc;
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::scheduler::{Kind, Scheduler, TimerStatus};
+use crate::slot::Slots;
+
+// setTimer command, start a timer on the server, returning how many seconds it
+// runs and when it ends, resolving calendar units in the configured timezone.
+pub struct SetTimer {
+    pub timezone: Tz,
+    pub scheduler: web::Data<Scheduler>,
+}
+
+impl CommandHandler for SetTimer {
+    fn intent(&self) -> &'static str {
+        "setTimer"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("snips/duration", "duration")]
+    }
+
+    fn optional_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("timer_name", "name")]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let duration = slots.duration("snips/duration", "duration")?;
+        let name = timer_name(slots)?;
+        let now = Utc::now().with_timezone(&self.timezone);
+        let end = match duration.resolve(&now) {
+            Some(e) => e,
+            None => return Err(NotUnderstood("the timer's duration is out of range".to_string())),
+        };
+        let seconds = end.signed_duration_since(now).num_milliseconds() as f64 / 1000.0;
+        if seconds <= 0.0 {
+            return Err(NotUnderstood("the timer has no duration".to_string()));
+        }
+        let timer = self
+            .scheduler
+            .add(Kind::Timer, name, now.with_timezone(&Utc), end.with_timezone(&Utc))
+            .map_err(NotUnderstood)?;
+        let human = match name {
+            Some(name) => format!("set {} timer for {} seconds", name, seconds),
+            None => format!("set timer for {} seconds", seconds),
+        };
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, seconds);
+        kakaia_response.data = Some(CommandData::new(&SetTimerData {
+            seconds,
+            end: end.to_rfc3339(),
+            exact: duration.exact,
+            id: timer.id,
+            name: timer.name,
+        }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, context: &Context) {
+    registry.register(Box::new(SetTimer {
+        timezone: context.config.timezone,
+        scheduler: context.scheduler.clone(),
+    }));
+}
+
+#[derive(Debug, Serialize)]
+pub struct SetTimerData {
+    seconds: f64,
+    // When the timer ends, in RFC 3339 format with the configured timezone.
+    end: String,
+    // False if the duration was approximate, such as "about an hour".
+    exact: bool,
+    // The timer started on the server.
+    id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+// The result of the commands acting on one timer, alarm or reminder.
+#[derive(Debug, Serialize)]
+pub struct TimerData {
+    pub timer: TimerStatus,
+}
+
+// The spoken name of a timer, such as "pasta", if any.
+pub fn timer_name(slots: &Slots) -> Result<Option<&str>, NotUnderstood> {
+    if !slots.has("timer_name", "name") {
+        return Ok(None);
+    }
+    Ok(Some(slots.custom("timer_name", "name")?))
+}
@@ -0,0 +1,73 @@
+use serde::Serialize;
+
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::slot::Slots;
+
+// simpleCalculation command, return result of calculation
+pub struct SimpleCalculation;
+
+impl CommandHandler for SimpleCalculation {
+    fn intent(&self) -> &'static str {
+        "simpleCalculation"
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("snips/number", "first"),
+            ("snips/number", "second"),
+            ("operation", "operation"),
+        ]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let first = slots.number("snips/number", "first")?;
+        let second = slots.number("snips/number", "second")?;
+        let operation = slots.custom("operation", "operation")?.to_string();
+        let operation_string;
+        let result = match operation.as_str() {
+            "plus" => {
+                operation_string = operation.to_string();
+                first + second
+            }
+            "minus" => {
+                operation_string = operation.to_string();
+                first - second
+            }
+            "multiply" => {
+                operation_string = "times".to_string();
+                first * second
+            }
+            "divide" => {
+                if second == 0.0 {
+                    return Err(NotUnderstood("cannot divide by zero".to_string()));
+                }
+                operation_string = "divided by".to_string();
+                first / second
+            }
+            _ => return Err(NotUnderstood(format!("unknown operation '{}'", operation))),
+        };
+        let mut kakaia_response = KakaiaResponse::new(
+            self.intent(),
+            format!("{} {} {} equals {}", first, operation_string, second, result).as_str(),
+            raw,
+            result,
+        );
+        kakaia_response.data = Some(CommandData::new(&SimpleCalculationData {
+            operands: vec![first, second],
+            operator: operation,
+            value: result,
+        }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, _context: &Context) {
+    registry.register(Box::new(SimpleCalculation));
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimpleCalculationData {
+    operands: Vec<f64>,
+    operator: String,
+    value: f64,
+}
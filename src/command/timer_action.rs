@@ -0,0 +1,84 @@
+use actix_web::web;
+use chrono::Utc;
+
+use crate::command::set_timer::{timer_name, TimerData};
+use crate::command::{CommandData, CommandHandler, Context, KakaiaResponse, NotUnderstood, Registry};
+use crate::scheduler::{self, Scheduler};
+use crate::slot::Slots;
+
+// What can be done to a timer once it is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimerAction {
+    Cancel,
+    Pause,
+    Resume,
+}
+
+impl TimerAction {
+    fn intent(self) -> &'static str {
+        match self {
+            TimerAction::Cancel => "cancelTimer",
+            TimerAction::Pause => "pauseTimer",
+            TimerAction::Resume => "resumeTimer",
+        }
+    }
+
+    // As spoken back, such as "paused the pasta timer".
+    fn past_tense(self) -> &'static str {
+        match self {
+            TimerAction::Cancel => "cancelled",
+            TimerAction::Pause => "paused",
+            TimerAction::Resume => "resumed",
+        }
+    }
+}
+
+// cancelTimer, pauseTimer and resumeTimer commands, act on the named timer, or
+// the only one if no name is spoken, returning the time it has left
+pub struct TimerCommand {
+    pub action: TimerAction,
+    pub scheduler: web::Data<Scheduler>,
+}
+
+impl CommandHandler for TimerCommand {
+    fn intent(&self) -> &'static str {
+        self.action.intent()
+    }
+
+    fn required_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    fn optional_slots(&self) -> &'static [(&'static str, &'static str)] {
+        &[("timer_name", "name")]
+    }
+
+    fn execute(&self, slots: &Slots, raw: &str) -> Result<KakaiaResponse, NotUnderstood> {
+        let name = timer_name(slots)?;
+        let now = Utc::now();
+        let timer = match self.action {
+            TimerAction::Cancel => self.scheduler.cancel(name, now),
+            TimerAction::Pause => self.scheduler.pause(name, now),
+            TimerAction::Resume => self.scheduler.resume(name, now),
+        }
+        .map_err(NotUnderstood)?;
+        let human = format!(
+            "{} {} with {} left",
+            self.action.past_tense(),
+            timer.name.as_ref().map_or("the timer".to_string(), |n| format!("the {} timer", n)),
+            scheduler::human_duration(timer.remaining)
+        );
+        let mut kakaia_response = KakaiaResponse::new(self.intent(), &human, raw, timer.remaining);
+        kakaia_response.data = Some(CommandData::new(&TimerData { timer }));
+        Ok(kakaia_response)
+    }
+}
+
+pub fn register(registry: &mut Registry, context: &Context) {
+    for action in &[TimerAction::Cancel, TimerAction::Pause, TimerAction::Resume] {
+        registry.register(Box::new(TimerCommand {
+            action: *action,
+            scheduler: context.scheduler.clone(),
+        }));
+    }
+}
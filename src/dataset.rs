@@ -0,0 +1,393 @@
+// Checks for the Snips NLU training dataset (nlu/dataset.yaml), catching
+// mistakes that snips-nlu silently accepts, such as utterances annotated with
+// slots the intent doesn't declare.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use serde_yaml::{Mapping, Value};
+
+const INTENT_KEYS: [&str; 4] = ["type", "name", "slots", "utterances"];
+const ENTITY_KEYS: [&str; 6] = [
+    "type",
+    "name",
+    "values",
+    "automatically_extensible",
+    "use_synonyms",
+    "matching_strictness",
+];
+
+// A mistake found in the dataset, in the document starting at line.
+#[derive(Debug)]
+pub struct Problem {
+    pub line: usize,
+    pub document: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.line, self.document, self.message)
+    }
+}
+
+// A slot annotation in an utterance: [slot](text) or [slot:entity](text).
+struct Annotation {
+    slot: String,
+    entity: Option<String>,
+}
+
+// A YAML document of the dataset, and the line it starts on.
+struct Document {
+    line: usize,
+    value: Mapping,
+}
+
+// Check the dataset, returning every problem found. Fails only if the dataset
+// can't be read or parsed at all.
+pub fn lint(path: &Path, min_utterances: usize) -> Result<Vec<Problem>, String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("failed to read {:?}: {}", path, e)),
+    };
+    lint_contents(&contents, min_utterances)
+}
+
+fn lint_contents(contents: &str, min_utterances: usize) -> Result<Vec<Problem>, String> {
+    let documents = split_documents(contents)?;
+    let mut problems = Vec::new();
+
+    // Every document is checked before the intents, so intents can refer to
+    // entities defined after them.
+    let mut entities: HashMap<String, usize> = HashMap::new();
+    let mut intents: HashMap<String, usize> = HashMap::new();
+    for document in &documents {
+        let kind = string(&document.value, "type").unwrap_or_default();
+        let name = string(&document.value, "name").unwrap_or_default();
+        let label = format!("{} '{}'", kind, name);
+        let problem = |message: String| Problem {
+            line: document.line,
+            document: label.clone(),
+            message,
+        };
+        let (names, keys): (&mut HashMap<String, usize>, &[&str]) = match kind.as_str() {
+            "intent" => (&mut intents, &INTENT_KEYS[..]),
+            "entity" => (&mut entities, &ENTITY_KEYS[..]),
+            _ => {
+                problems.push(problem(format!("unknown document type '{}'", kind)));
+                continue;
+            }
+        };
+        if name.is_empty() {
+            problems.push(problem("has no name".to_string()));
+        } else if let Some(line) = names.insert(name.clone(), document.line) {
+            problems.push(problem(format!("is already defined at line {}", line)));
+        }
+        for (key, _) in document.value.iter() {
+            let key = key.as_str().unwrap_or("");
+            if !keys.contains(&key) {
+                problems.push(problem(format!("unknown key '{}'", key)));
+            }
+        }
+        if kind == "entity" {
+            lint_entity(&document.value, &mut |m| problems.push(problem(m)));
+        }
+    }
+
+    for document in &documents {
+        if string(&document.value, "type").as_deref() != Some("intent") {
+            continue;
+        }
+        let label = format!("intent '{}'", string(&document.value, "name").unwrap_or_default());
+        let mut report = |message: String| {
+            problems.push(Problem {
+                line: document.line,
+                document: label.clone(),
+                message,
+            })
+        };
+        lint_intent(&document.value, &entities, min_utterances, &mut report);
+    }
+
+    problems.sort_by_key(|p| p.line);
+    Ok(problems)
+}
+
+fn lint_intent(
+    intent: &Mapping,
+    entities: &HashMap<String, usize>,
+    min_utterances: usize,
+    report: &mut dyn FnMut(String),
+) {
+    // Declared slots, by name.
+    let mut declared: BTreeMap<String, String> = BTreeMap::new();
+    for slot in sequence(intent, "slots") {
+        let slot = match slot.as_mapping() {
+            Some(s) => s,
+            None => {
+                report("slots must be a list of names and entities".to_string());
+                continue;
+            }
+        };
+        let name = string(slot, "name").unwrap_or_default();
+        let entity = match string(slot, "entity") {
+            Some(e) => e,
+            None => {
+                report(format!("slot '{}' has no entity", name));
+                continue;
+            }
+        };
+        if !is_builtin(&entity) && !entities.contains_key(&entity) {
+            report(format!("slot '{}' uses undefined entity '{}'", name, entity));
+        }
+        if declared.insert(name.clone(), entity).is_some() {
+            report(format!("slot '{}' is declared more than once", name));
+        }
+    }
+
+    let utterances = sequence(intent, "utterances");
+    if utterances.len() < min_utterances {
+        report(format!(
+            "has {} utterances, at least {} are needed to train it reliably",
+            utterances.len(),
+            min_utterances
+        ));
+    }
+
+    // Slots used by the utterances, with the number of utterances using each.
+    let mut used: BTreeMap<String, usize> = BTreeMap::new();
+    for utterance in utterances {
+        let utterance = match utterance.as_str() {
+            Some(u) => u,
+            None => {
+                report("utterances must be strings".to_string());
+                continue;
+            }
+        };
+        let annotations = match annotations(utterance) {
+            Ok(a) => a,
+            Err(e) => {
+                report(format!("{} in utterance \"{}\"", e, utterance));
+                continue;
+            }
+        };
+        for annotation in annotations {
+            *used.entry(annotation.slot.clone()).or_insert(0) += 1;
+            if let (Some(declared), Some(entity)) = (declared.get(&annotation.slot), &annotation.entity) {
+                if declared != entity {
+                    report(format!(
+                        "slot '{}' is declared as {} but annotated as {}, in utterance \"{}\"",
+                        annotation.slot, declared, entity, utterance
+                    ));
+                }
+            }
+        }
+    }
+
+    for (name, count) in &used {
+        if !declared.contains_key(name) {
+            report(format!("slot '{}' is used in {} utterances, but not declared", name, count));
+        }
+    }
+    for name in declared.keys() {
+        if !used.contains_key(name) {
+            report(format!("slot '{}' is declared but never used", name));
+        }
+    }
+}
+
+fn lint_entity(entity: &Mapping, report: &mut dyn FnMut(String)) {
+    // Every value and synonym, case-insensitively, with the value it belongs to.
+    let mut seen: HashMap<String, String> = HashMap::new();
+    for value in sequence(entity, "values") {
+        let names: Vec<String> = match value {
+            Value::String(s) => vec![s.to_string()],
+            Value::Sequence(synonyms) => synonyms.iter().filter_map(scalar).collect(),
+            _ => {
+                report("values must be strings or lists of synonyms".to_string());
+                continue;
+            }
+        };
+        let canonical = match names.first() {
+            Some(c) => c.to_string(),
+            None => {
+                report("has an empty list of synonyms".to_string());
+                continue;
+            }
+        };
+        for name in names {
+            if let Some(other) = seen.insert(name.to_lowercase(), canonical.clone()) {
+                if other == canonical {
+                    report(format!("'{}' is repeated in the synonyms of '{}'", name, canonical));
+                } else {
+                    report(format!("'{}' is a synonym of both '{}' and '{}'", name, other, canonical));
+                }
+            }
+        }
+    }
+}
+
+// Split the dataset on "---" lines, parsing each YAML document and skipping
+// those that are empty, such as the leading comments, which serde_yaml can't
+// parse.
+fn split_documents(contents: &str) -> Result<Vec<Document>, String> {
+    let mut documents = Vec::new();
+    let mut start = 1;
+    let mut text = String::new();
+    for (index, line) in contents.lines().chain(std::iter::once("---")).enumerate() {
+        if line.trim_end() != "---" {
+            text.push_str(line);
+            text.push('\n');
+            continue;
+        }
+        let empty = text.lines().all(|l| l.trim().is_empty() || l.trim_start().starts_with('#'));
+        if !empty {
+            let value: Value = match serde_yaml::from_str(&text) {
+                Ok(v) => v,
+                Err(e) => return Err(format!("invalid YAML in the document at line {}: {}", start, e)),
+            };
+            match value {
+                Value::Null => (),
+                Value::Mapping(value) => documents.push(Document { line: start, value }),
+                _ => return Err(format!("the document at line {} is not a mapping", start)),
+            }
+        }
+        start = index + 2;
+        text.clear();
+    }
+    Ok(documents)
+}
+
+// Find the slot annotations of an utterance.
+fn annotations(utterance: &str) -> Result<Vec<Annotation>, String> {
+    let mut annotations = Vec::new();
+    let mut rest = utterance;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let close = match rest.find("](") {
+            Some(c) => c,
+            None => return Err("unterminated slot annotation".to_string()),
+        };
+        let slot = &rest[..close];
+        rest = &rest[close + 2..];
+        match rest.find(')') {
+            Some(end) if end > 0 => rest = &rest[end + 1..],
+            _ => return Err(format!("slot '{}' has no text", slot)),
+        }
+        let mut parts = slot.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err("slot annotation has no name".to_string());
+        }
+        annotations.push(Annotation {
+            slot: name.to_string(),
+            entity: parts.next().map(|e| e.trim().to_string()),
+        });
+    }
+    Ok(annotations)
+}
+
+fn is_builtin(entity: &str) -> bool {
+    entity.starts_with("snips/")
+}
+
+fn string(mapping: &Mapping, key: &str) -> Option<String> {
+    mapping.get(&Value::String(key.to_string())).and_then(scalar)
+}
+
+fn sequence<'a>(mapping: &'a Mapping, key: &str) -> &'a [Value] {
+    match mapping.get(&Value::String(key.to_string())) {
+        Some(Value::Sequence(s)) => s.as_slice(),
+        _ => &[],
+    }
+}
+
+// Synonyms such as "C" or "no" may be parsed as other scalars.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTITY: &str = "
+type: entity
+name: unit
+values:
+  - [meter, meters, m]
+  - [foot, feet, ft]
+";
+
+    const INTENT: &str = "
+type: intent
+name: convertUnit
+slots:
+  - name: to
+    entity: unit
+utterances:
+  - convert to [to](meters)
+  - how many [to](feet) is it
+";
+
+    fn messages(dataset: &str) -> Vec<String> {
+        lint_contents(dataset, 2).unwrap().into_iter().map(|p| p.message).collect()
+    }
+
+    #[test]
+    fn clean_dataset() {
+        assert!(messages(&format!("# header\n---{}---{}", ENTITY, INTENT)).is_empty());
+    }
+
+    #[test]
+    fn undeclared_slot() {
+        let intent = INTENT.replace("how many [to](feet)", "how many [from:unit](feet)");
+        assert_eq!(messages(&format!("{}---{}", ENTITY, intent)), vec![
+            "slot 'from' is used in 1 utterances, but not declared"
+        ]);
+    }
+
+    #[test]
+    fn undefined_entity() {
+        let problems = lint_contents(INTENT, 2).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, 1);
+        assert_eq!(problems[0].document, "intent 'convertUnit'");
+        assert_eq!(problems[0].message, "slot 'to' uses undefined entity 'unit'");
+    }
+
+    #[test]
+    fn duplicate_synonym() {
+        let entity = ENTITY.replace("[foot, feet, ft]", "[foot, feet, M]");
+        assert_eq!(messages(&format!("{}---{}", entity, INTENT)), vec![
+            "'M' is a synonym of both 'meter' and 'foot'"
+        ]);
+        let entity = ENTITY.replace("[foot, feet, ft]", "[foot, feet, Feet]");
+        assert_eq!(messages(&format!("{}---{}", entity, INTENT)), vec![
+            "'Feet' is repeated in the synonyms of 'foot'"
+        ]);
+    }
+
+    #[test]
+    fn too_few_utterances() {
+        let dataset = format!("{}---{}", ENTITY, INTENT);
+        assert!(lint_contents(&dataset, 2).unwrap().is_empty());
+        let problems = lint_contents(&dataset, 3).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].message,
+            "has 2 utterances, at least 3 are needed to train it reliably"
+        );
+    }
+
+    #[test]
+    fn invalid_yaml() {
+        assert!(lint_contents("type: [intent", 2).is_err());
+        assert!(lint_contents("- intent", 2).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::Serialize;
+
+use crate::command::KakaiaResponse;
+
+// Everything that can go wrong handling a request. Each failure is answered
+// with an HTTP status and a stable code in the "error" field of the response,
+// which clients can match on without parsing the message.
+#[derive(Debug)]
+pub enum KakaiaError {
+    // The upload couldn't be read, or declares invalid parameters.
+    Upload(String),
+    // The upload exceeds the --bytes limit.
+    TooLarge(usize),
+    // The audio file is corrupt or truncated.
+    Decode(String),
+    // The audio file isn't in a supported format.
+    Format(String),
+    // The audio has no usable channel, such as the one selected by --downmix.
+    Channel(String),
+    // The speech-to-text backend failed.
+    Recognition(String),
+    // The NLU engine failed to parse the text.
+    Nlu(String),
+    // A copy of the audio or text couldn't be stored with --store.
+    Archive(String),
+    // Something went wrong on the server regardless of the request, such as a
+    // decoder failing to initialize.
+    Internal(String),
+    // The request was dropped before it could be processed.
+    Canceled,
+    // No model instance became available within --checkout-timeout.
+    Busy,
+    // Audio was streamed faster than it could be recognized.
+    TooFast,
+}
+
+// The "error" field of a response.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorDescription {
+    pub code: String,
+    pub message: String,
+}
+
+impl KakaiaError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            KakaiaError::Upload(_) => "invalid_upload",
+            KakaiaError::TooLarge(_) => "upload_too_large",
+            KakaiaError::Decode(_) => "decode_failed",
+            KakaiaError::Format(_) => "unsupported_format",
+            KakaiaError::Channel(_) => "invalid_channel",
+            KakaiaError::Recognition(_) => "recognition_failed",
+            KakaiaError::Nlu(_) => "nlu_failed",
+            KakaiaError::Archive(_) => "archive_failed",
+            KakaiaError::Internal(_) => "internal_error",
+            KakaiaError::Canceled => "canceled",
+            KakaiaError::Busy => "busy",
+            KakaiaError::TooFast => "stream_too_fast",
+        }
+    }
+
+    // Client errors are 4xx, server errors are 5xx.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            KakaiaError::Upload(_) => StatusCode::BAD_REQUEST,
+            KakaiaError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            KakaiaError::Decode(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            KakaiaError::Format(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            KakaiaError::Channel(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            KakaiaError::Recognition(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            KakaiaError::Nlu(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            KakaiaError::Archive(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            KakaiaError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            KakaiaError::Canceled => StatusCode::SERVICE_UNAVAILABLE,
+            KakaiaError::Busy => StatusCode::SERVICE_UNAVAILABLE,
+            KakaiaError::TooFast => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    // Short description for the legacy "human" field.
+    fn human(&self) -> &'static str {
+        match self {
+            KakaiaError::Upload(_) => "unexpected error reading audio upload",
+            KakaiaError::TooLarge(_) => "audio file too large",
+            KakaiaError::Decode(_) => "unexpected error decoding audio",
+            KakaiaError::Format(_) => "unsupported audio format",
+            KakaiaError::Channel(_) => "unsupported audio channels",
+            KakaiaError::Recognition(_) => "unexpected error converting audio to text",
+            KakaiaError::Nlu(_) => "unexpected error parsing text",
+            KakaiaError::Archive(_) => "unexpected error archiving a copy of audio file",
+            KakaiaError::Internal(_) => "unexpected server error",
+            KakaiaError::Canceled => "request canceled",
+            KakaiaError::Busy => "server busy",
+            KakaiaError::TooFast => "audio streamed too fast",
+        }
+    }
+
+    // The response describing this error, with the text recognized so far.
+    pub fn response(&self, raw: &str) -> KakaiaResponse {
+        let mut kakaia_response = KakaiaResponse::new("none", self.human(), raw, 0.0);
+        kakaia_response.error = Some(ErrorDescription {
+            code: self.code().to_string(),
+            message: self.to_string(),
+        });
+        kakaia_response
+    }
+
+    pub fn http_response(&self, raw: &str) -> HttpResponse {
+        eprintln!("{}: {}", self.code(), self);
+        HttpResponse::build(self.status())
+            .content_type("application/json")
+            .body(self.response(raw).to_json_string())
+    }
+}
+
+impl fmt::Display for KakaiaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KakaiaError::Upload(e)
+            | KakaiaError::Decode(e)
+            | KakaiaError::Format(e)
+            | KakaiaError::Channel(e)
+            | KakaiaError::Recognition(e)
+            | KakaiaError::Nlu(e)
+            | KakaiaError::Archive(e)
+            | KakaiaError::Internal(e) => write!(f, "{}", e),
+            KakaiaError::TooLarge(limit) => write!(f, "audio file exceeds the limit of {} bytes", limit),
+            KakaiaError::Canceled => write!(f, "processing canceled"),
+            KakaiaError::Busy => write!(f, "no model instance became available in time"),
+            KakaiaError::TooFast => write!(f, "audio is streamed faster than it can be recognized"),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::fs::File;
+
+use crate::speech::{SpeechStream, SpeechToText};
+
+// Deterministic speech-to-text backend for testing without the DeepSpeech native
+// client: audio is identified by the md5 hash of the 16 kHz mono samples passed
+// to the backend (as little-endian bytes, after silence trimming), and mapped to
+// a transcript loaded from a JSON object of "hash": "transcript".
+pub struct FixtureSpeech {
+    transcripts: HashMap<String, String>,
+}
+
+impl FixtureSpeech {
+    pub fn new(path: &str) -> Self {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("FATAL ERROR, failed to open fixtures '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        let transcripts: HashMap<String, String> = match serde_json::from_reader(file) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("FATAL ERROR, failed to parse fixtures '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        FixtureSpeech { transcripts }
+    }
+
+    pub fn hash(audio_buffer: &[i16]) -> String {
+        let mut bytes = Vec::with_capacity(audio_buffer.len() * 2);
+        for sample in audio_buffer {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        format!("{:x}", md5::compute(&bytes))
+    }
+}
+
+impl SpeechToText for FixtureSpeech {
+    fn speech_to_text(&mut self, audio_buffer: &[i16]) -> Result<String, String> {
+        let hash = FixtureSpeech::hash(audio_buffer);
+        match self.transcripts.get(&hash) {
+            Some(t) => Ok(t.to_string()),
+            None => Err(format!("no fixture for audio hash {}", hash)),
+        }
+    }
+
+    fn create_stream(&mut self) -> Result<Box<dyn SpeechStream + '_>, String> {
+        Ok(Box::new(FixtureStream {
+            fixture: self,
+            audio_buffer: Vec::new(),
+        }))
+    }
+}
+
+// Buffers all streamed samples, as the fixture can only be identified once the
+// complete audio has been received.
+pub struct FixtureStream<'a> {
+    fixture: &'a mut FixtureSpeech,
+    audio_buffer: Vec<i16>,
+}
+
+impl<'a> SpeechStream for FixtureStream<'a> {
+    fn feed_audio(&mut self, audio_buffer: &[i16]) {
+        self.audio_buffer.extend_from_slice(audio_buffer);
+    }
+
+    fn intermediate_decode(&mut self) -> Result<String, String> {
+        Ok("".to_string())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<String, String> {
+        let audio_buffer = std::mem::replace(&mut self.audio_buffer, Vec::new());
+        self.fixture.speech_to_text(&audio_buffer)
+    }
+}
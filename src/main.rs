@@ -1,13 +1,36 @@
-use std::sync::Mutex;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use actix_web::{web, App, FromRequest, HttpServer};
+use actix_web::{web, App, HttpServer};
+use chrono_tz::Tz;
 use structopt::StructOpt;
 
+use crate::audio::Downmix;
+use crate::command::Registry;
 use crate::nlu::NLU;
-use crate::speech::KakaiaDeepSpeech;
+use crate::pool::Pool;
+use crate::resample::Interpolation;
+use crate::scheduler::Scheduler;
 
+pub mod audio;
+pub mod codec;
+pub mod command;
+pub mod dataset;
+pub mod error;
+pub mod fixture;
 pub mod nlu;
+pub mod pool;
+pub mod resample;
+pub mod scheduler;
+pub mod slot;
 pub mod speech;
+pub mod stream;
+pub mod unit;
+pub mod upload;
+pub mod vad;
+pub mod webm;
 
 #[derive(StructOpt, Debug, Clone)]
 #[structopt(name = "kakaia")]
@@ -17,45 +40,413 @@ pub struct Configuration {
     #[structopt(short, long, parse(from_occurrences))]
     verbose: u8,
     */
+    /// Configuration file (TOML), setting any of the long options below by name
+    #[structopt(short, long, env = "KAKAIA_CONFIG", parse(from_os_str))]
+    config: Option<PathBuf>,
+
     /// Listen on IP:port
-    #[structopt(short, long, default_value = "0.0.0.0:8088")]
+    #[structopt(short, long, env = "KAKAIA_LISTEN", default_value = "0.0.0.0:8088")]
     listen: String,
 
     /// Max bytes for audio files
-    #[structopt(short, long, default_value = "4194304")]
+    #[structopt(short, long, env = "KAKAIA_BYTES", default_value = "4194304")]
     bytes: usize,
 
     /// Permanently store a copy of audio and text
     #[structopt(short, long)]
     store: bool,
+
+    /// Downmix multi-channel audio by averaging all channels ("average"), or by selecting a channel (0, 1, ...)
+    #[structopt(short, long, env = "KAKAIA_DOWNMIX", default_value = "average")]
+    downmix: Downmix,
+
+    /// Interpolation used to convert audio to 16 kHz: band-limited "sinc", or faster "linear"
+    #[structopt(long, env = "KAKAIA_INTERPOLATION", default_value = "sinc", possible_values = &["sinc", "linear"])]
+    interpolation: Interpolation,
+
+    /// Don't trim silence before speech recognition
+    #[structopt(long)]
+    no_vad: bool,
+
+    /// Minimum energy of speech above the background noise, in dB, when trimming silence
+    #[structopt(long, env = "KAKAIA_VAD_MARGIN", default_value = "12.0")]
+    vad_margin: f64,
+
+    /// Number of speech and NLU model instances, limiting concurrent requests
+    #[structopt(short, long, env = "KAKAIA_INSTANCES", default_value = "1")]
+    instances: usize,
+
+    /// Seconds to wait for a free model instance before answering 503
+    #[structopt(long, env = "KAKAIA_CHECKOUT_TIMEOUT", default_value = "30")]
+    checkout_timeout: u64,
+
+    /// Speech-to-text backend
+    #[structopt(long, env = "KAKAIA_BACKEND", default_value = "deepspeech", possible_values = &["deepspeech", "fixture"])]
+    backend: String,
+
+    /// JSON file mapping audio hashes to transcripts, for the fixture backend
+    #[structopt(long, env = "KAKAIA_FIXTURES", default_value = "test/fixtures.json")]
+    fixtures: String,
+
+    /// Directory containing the DeepSpeech model files
+    #[structopt(long, env = "DEEPSPEECH_MODELS", default_value = "models/", parse(from_os_str))]
+    models: PathBuf,
+
+    /// DeepSpeech acoustic model, relative to --models
+    #[structopt(long, env = "KAKAIA_MODEL", default_value = "output_graph.pb", parse(from_os_str))]
+    model: PathBuf,
+
+    /// DeepSpeech language model, relative to --models
+    #[structopt(long, env = "KAKAIA_LM", default_value = "lm.binary", parse(from_os_str))]
+    lm: PathBuf,
+
+    /// DeepSpeech language model trie, relative to --models
+    #[structopt(long, env = "KAKAIA_TRIE", default_value = "trie", parse(from_os_str))]
+    trie: PathBuf,
+
+    /// Decode without the language model (faster, but less accurate)
+    #[structopt(long)]
+    no_lm: bool,
+
+    // The decoder defaults are taken from the C++ sources of the client.
+    /// Beam width of the DeepSpeech decoder
+    #[structopt(long, env = "KAKAIA_BEAM_WIDTH", default_value = "500")]
+    beam_width: u16,
+
+    /// Weight of the language model (alpha)
+    #[structopt(long, env = "KAKAIA_LM_WEIGHT", default_value = "0.75")]
+    lm_weight: f32,
+
+    /// Weight of the number of valid words (beta)
+    #[structopt(long, env = "KAKAIA_VALID_WORD_COUNT_WEIGHT", default_value = "1.85")]
+    valid_word_count_weight: f32,
+
+    /// Directory of the trained Snips NLU engine
+    #[structopt(long, env = "KAKAIA_NLU_ENGINE", default_value = "nlu/kakaia_engine/", parse(from_os_str))]
+    nlu_engine: PathBuf,
+
+    /// Timezone in which spoken dates and durations are resolved, such as "Europe/Rome"
+    #[structopt(long, env = "KAKAIA_TIMEZONE", default_value = "UTC")]
+    timezone: Tz,
+
+    /// File in which timers are kept across restarts
+    #[structopt(long, env = "KAKAIA_SCHEDULE", default_value = "schedule.json", parse(from_os_str))]
+    schedule: PathBuf,
+
+    /// Minimum probability of a recognized intent, below which commands are not understood
+    #[structopt(long, env = "KAKAIA_MIN_PROBABILITY", default_value = "0.5")]
+    min_probability: f64,
+
+    /// Number of alternative intents to include in responses, for clients to disambiguate
+    #[structopt(long, env = "KAKAIA_ALTERNATIVES", default_value = "0")]
+    alternatives: usize,
+
+    /// Refuse to start if the NLU engine's intents and slots don't match the commands
+    #[structopt(long)]
+    strict_intents: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+// Tools run instead of the server.
+#[derive(StructOpt, Debug, Clone)]
+pub enum Command {
+    /// Work with the NLU training dataset
+    Dataset(DatasetCommand),
+    /// Work with the trained NLU engine
+    Engine(EngineCommand),
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum DatasetCommand {
+    /// Check the dataset for mistakes, exiting non-zero if any are found
+    Lint {
+        /// Dataset in the snips-nlu YAML format
+        #[structopt(default_value = "nlu/dataset.yaml", parse(from_os_str))]
+        dataset: PathBuf,
+
+        /// Minimum number of utterances of each intent
+        #[structopt(long, default_value = "5")]
+        min_utterances: usize,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum EngineCommand {
+    /// Check the engine's intents and slots match the commands, exiting non-zero if not
+    Check,
+}
+
+impl Configuration {
+    // Load the configuration from the command line, the environment and an
+    // optional configuration file, in that order of precedence.
+    pub fn load() -> Self {
+        let args: Vec<OsString> = env::args_os().collect();
+        let matches = Configuration::clap().get_matches_from(&args);
+        let config = Configuration::from_clap(&matches);
+        let path = match &config.config {
+            Some(p) => p,
+            None => return config,
+        };
+
+        let contents = match std::fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("FATAL ERROR, failed to read --config {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        let table: toml::value::Table = match toml::from_str(&contents) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("FATAL ERROR, failed to parse --config {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+
+        // Options from the file are inserted ahead of the command line, unless
+        // already set on the command line or in the environment.
+        let mut file_args: Vec<OsString> = Vec::new();
+        for (key, value) in table {
+            let name = key.replace('-', "_");
+            let option = format!("--{}", key.replace('_', "-"));
+            if name == "config" || matches.occurrences_of(&name) > 0 || env::var_os(env_name(&name)).is_some() {
+                continue;
+            }
+            let value = match value {
+                toml::Value::Boolean(true) => {
+                    file_args.push(option.into());
+                    continue;
+                }
+                toml::Value::Boolean(false) => continue,
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                _ => {
+                    eprintln!("FATAL ERROR, unsupported value for '{}' in --config {:?}", key, path);
+                    std::process::exit(1);
+                }
+            };
+            file_args.push(option.into());
+            file_args.push(value.into());
+        }
+
+        let mut merged = args[..1].to_vec();
+        merged.extend(file_args);
+        merged.extend_from_slice(&args[1..]);
+        Configuration::from_iter(merged)
+    }
+
+    // Confirm all configured files exist before loading anything, so a typo
+    // doesn't surface as an obscure model loading error.
+    pub fn validate(&self) {
+        if self.instances == 0 {
+            eprintln!("FATAL ERROR, at least one instance is required");
+            std::process::exit(1);
+        }
+        if self.min_probability < 0.0 || self.min_probability > 1.0 {
+            eprintln!("FATAL ERROR, --min-probability must be between 0 and 1");
+            std::process::exit(1);
+        }
+        if self.backend == "deepspeech" {
+            require_path("--models", &self.models);
+            require_path("--model", &self.model_path());
+            if !self.no_lm {
+                require_path("--lm", &self.lm_path());
+                require_path("--trie", &self.trie_path());
+            }
+        } else {
+            require_path("--fixtures", Path::new(&self.fixtures));
+        }
+        require_path("--nlu-engine", &self.nlu_engine);
+        require_path("--nlu-engine", &self.nlu_engine.join("nlu_engine.json"));
+    }
+
+    pub fn model_path(&self) -> PathBuf {
+        self.models.join(&self.model)
+    }
+
+    pub fn lm_path(&self) -> PathBuf {
+        self.models.join(&self.lm)
+    }
+
+    pub fn trie_path(&self) -> PathBuf {
+        self.models.join(&self.trie)
+    }
+}
+
+// The environment variable setting an option, as declared above.
+fn env_name(name: &str) -> String {
+    match name {
+        "models" => "DEEPSPEECH_MODELS".to_string(),
+        _ => format!("KAKAIA_{}", name.to_uppercase()),
+    }
+}
+
+fn require_path(option: &str, path: &Path) {
+    if !path.exists() {
+        eprintln!("FATAL ERROR, {} {:?} does not exist", option, path);
+        std::process::exit(1);
+    }
+}
+
+// Run a tool, exiting with its status.
+fn run(command: &Command, config: &Configuration) -> ! {
+    match command {
+        Command::Dataset(DatasetCommand::Lint {
+            dataset,
+            min_utterances,
+        }) => {
+            let problems = match dataset::lint(dataset, *min_utterances) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("FATAL ERROR, {}", e);
+                    std::process::exit(2);
+                }
+            };
+            for problem in &problems {
+                println!("{}:{}", dataset.display(), problem);
+            }
+            if problems.is_empty() {
+                println!("{}: no problems found", dataset.display());
+                std::process::exit(0);
+            }
+            println!("{}: {} problems found", dataset.display(), problems.len());
+            std::process::exit(1);
+        }
+        Command::Engine(EngineCommand::Check) => {
+            let engine = config.nlu_engine.display();
+            // The commands are registered as the server would, without
+            // starting its timers.
+            let scheduler = match Scheduler::load(&config.schedule, config.timezone) {
+                Ok(s) => web::Data::new(s),
+                Err(e) => {
+                    eprintln!("FATAL ERROR, {}", e);
+                    std::process::exit(2);
+                }
+            };
+            let mismatches = match Registry::new(config, &scheduler).check(&config.nlu_engine) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("FATAL ERROR, {}", e);
+                    std::process::exit(2);
+                }
+            };
+            for mismatch in &mismatches {
+                println!("{}: {}", engine, mismatch);
+            }
+            if mismatches.is_empty() {
+                println!("{}: the engine matches the commands", engine);
+                std::process::exit(0);
+            }
+            println!("{}: {} mismatches found", engine, mismatches.len());
+            std::process::exit(1);
+        }
+    }
+}
+
+// Warn loudly about intents the commands don't expect, or fail with
+// --strict-intents.
+fn check_intents(registry: &Registry, config: &Configuration) {
+    let mismatches = match registry.check(&config.nlu_engine) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("FATAL ERROR, {}", e);
+            std::process::exit(1);
+        }
+    };
+    for mismatch in &mismatches {
+        eprintln!("WARNING, {}", mismatch);
+    }
+    if config.strict_intents && !mismatches.is_empty() {
+        eprintln!(
+            "FATAL ERROR, the NLU engine {:?} doesn't match the commands",
+            config.nlu_engine
+        );
+        std::process::exit(1);
+    }
 }
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     // Configuration structure for server configuration
-    let config_server = Configuration::from_args();
+    let config_server = Configuration::load();
+    if let Some(command) = &config_server.command {
+        run(command, &config_server);
+    }
+    config_server.validate();
+    // The NLU engine resolves dates and times such as "tomorrow at 7am" on the
+    // local clock, so make that the configured timezone before anything reads
+    // it.
+    env::set_var("TZ", config_server.timezone.name());
     // Configuration structure for client configuration
     let config_web = config_server.clone();
-    // Initialize DeepSpeech models
-    println!("Loading Deepspeech model...");
-    let deepspeech_data = web::Data::new(Mutex::new(KakaiaDeepSpeech::new()));
-    // Initialize Snips NLU engine
-    println!("Loading Snips NLU engine...");
-    let nlu_data = web::Data::new(Mutex::new(NLU::new()));
+    // Timers, running in the background
+    let scheduler = match Scheduler::load(&config_server.schedule, config_server.timezone) {
+        Ok(s) => web::Data::new(s),
+        Err(e) => {
+            eprintln!("FATAL ERROR, {}", e);
+            std::process::exit(1);
+        }
+    };
+    scheduler::run(scheduler.clone());
+    // Commands run for recognized intents
+    let registry = web::Data::new(Registry::new(&config_server, &scheduler));
+    check_intents(&registry, &config_server);
+    // Initialize speech-to-text backends
+    let speech_pool = web::Data::new(Pool::new(
+        (0..config_server.instances)
+            .map(|_| speech::load_backend(&config_server))
+            .collect(),
+        Duration::from_secs(config_server.checkout_timeout),
+    ));
+    // Initialize Snips NLU engines
+    let nlu_pool = web::Data::new(Pool::new(
+        (0..config_server.instances)
+            .map(|_| {
+                println!("Loading Snips NLU engine...");
+                NLU::new(&config_server)
+            })
+            .collect(),
+        Duration::from_secs(config_server.checkout_timeout),
+    ));
     println!("Launched.");
 
     HttpServer::new(move || {
-        App::new().service(
-            web::resource("/convert/audio/text")
-                .data(config_web.clone())
-                .app_data(deepspeech_data.clone())
-                .app_data(nlu_data.clone())
-                .app_data(String::configure(|cfg| {
-                    // limit audio file size in bytes (defaults to 4MB)
-                    cfg.limit(config_web.bytes)
-                }))
-                .route(web::post().to(speech::_audio_to_text)),
-        )
+        App::new()
+            .service(
+                web::resource("/convert/audio/text")
+                    .data(config_web.clone())
+                    .app_data(registry.clone())
+                    .app_data(speech_pool.clone())
+                    .app_data(nlu_pool.clone())
+                    .route(web::post().to(speech::_audio_to_text)),
+            )
+            .service(
+                web::resource("/convert/audio/stream")
+                    .data(config_web.clone())
+                    .app_data(registry.clone())
+                    .app_data(speech_pool.clone())
+                    .app_data(nlu_pool.clone())
+                    .route(web::get().to(stream::_audio_stream)),
+            )
+            .service(
+                web::resource("/convert/text/command")
+                    .app_data(registry.clone())
+                    .app_data(nlu_pool.clone())
+                    .route(web::post().to(command::_text_to_command)),
+            )
+            .service(
+                web::resource("/timers")
+                    .app_data(scheduler.clone())
+                    .route(web::get().to(scheduler::_timers)),
+            )
+            .service(
+                web::resource("/timers/events")
+                    .app_data(scheduler.clone())
+                    .route(web::get().to(scheduler::_timer_events)),
+            )
     })
     .bind(&config_server.listen)?
     .run()
@@ -0,0 +1,92 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::KakaiaError;
+
+// A fixed pool of model instances, allowing as many concurrent requests as
+// there are instances. Checking out an instance blocks until one is returned,
+// so this must only be done off the async executor (ie, in web::block).
+pub struct Pool<T> {
+    inner: Arc<PoolInner<T>>,
+}
+
+struct PoolInner<T> {
+    items: Mutex<Vec<T>>,
+    available: Condvar,
+    // How long to wait for an instance before giving up.
+    timeout: Duration,
+}
+
+// An instance checked out of the pool, returned to the pool when dropped.
+pub struct Pooled<T> {
+    pool: Pool<T>,
+    item: Option<T>,
+}
+
+impl<T> Pool<T> {
+    pub fn new(items: Vec<T>, timeout: Duration) -> Self {
+        Pool {
+            inner: Arc::new(PoolInner {
+                items: Mutex::new(items),
+                available: Condvar::new(),
+                timeout,
+            }),
+        }
+    }
+
+    // Block until an instance is available, and take it out of the pool, or
+    // fail with Busy if none is returned within the timeout.
+    pub fn checkout(&self) -> Result<Pooled<T>, KakaiaError> {
+        let deadline = Instant::now() + self.inner.timeout;
+        let mut items = self.inner.items.lock().unwrap();
+        loop {
+            if let Some(item) = items.pop() {
+                return Ok(Pooled {
+                    pool: self.clone(),
+                    item: Some(item),
+                });
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(KakaiaError::Busy);
+            }
+            items = self.inner.available.wait_timeout(items, deadline - now).unwrap().0;
+        }
+    }
+
+    fn checkin(&self, item: T) {
+        self.inner.items.lock().unwrap().push(item);
+        self.inner.available.notify_one();
+    }
+}
+
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Pool {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.item.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            self.pool.checkin(item);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use audrey::sample::interpolate::{Converter, Linear};
+use audrey::sample::signal::{from_iter, Signal};
+
+// Zero crossings of the sinc function on each side of the windowed filter.
+const ZERO_CROSSINGS: usize = 16;
+// Filter coefficients precomputed per zero crossing, interpolated in between.
+const RESOLUTION: usize = 512;
+// Fraction of the lower Nyquist frequency kept, leaving room for the filter's
+// transition band.
+const ROLLOFF: f64 = 0.945;
+
+// How to convert audio to the sample rate DeepSpeech expects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    // Band-limited windowed-sinc interpolation, avoiding aliasing.
+    Sinc,
+    // Linear interpolation, faster but aliases when downsampling.
+    Linear,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(interpolation: &str) -> Result<Self, Self::Err> {
+        match interpolation {
+            "sinc" => Ok(Interpolation::Sinc),
+            "linear" => Ok(Interpolation::Linear),
+            _ => Err(format!(
+                "'{}' is not a supported interpolation (sinc, linear)",
+                interpolation
+            )),
+        }
+    }
+}
+
+// Convert mono samples from one sample rate to another.
+pub fn resample(samples: Vec<i16>, from_hz: u32, to_hz: u32, interpolation: Interpolation) -> Vec<i16> {
+    if from_hz == to_hz || samples.is_empty() {
+        return samples;
+    }
+    match interpolation {
+        Interpolation::Sinc => sinc(&samples, from_hz, to_hz),
+        Interpolation::Linear => linear(samples, from_hz, to_hz),
+    }
+}
+
+fn linear(samples: Vec<i16>, from_hz: u32, to_hz: u32) -> Vec<i16> {
+    let interpolator = Linear::new([0i16], [0]);
+    let conv = Converter::from_hz_to_hz(
+        from_iter(samples.into_iter().map(|s| [s])),
+        interpolator,
+        from_hz as f64,
+        to_hz as f64,
+    );
+    conv.until_exhausted().map(|v| v[0]).collect()
+}
+
+// Windowed-sinc resampling: each output sample is the sum of the surrounding
+// input samples weighted by a Blackman windowed sinc low-pass filter, with the
+// cutoff below the Nyquist frequency of both sample rates.
+fn sinc(samples: &[i16], from_hz: u32, to_hz: u32) -> Vec<i16> {
+    let ratio = to_hz as f64 / from_hz as f64;
+    // Cutoff as a fraction of the input Nyquist frequency.
+    let cutoff = ratio.min(1.0) * ROLLOFF;
+    // Half width of the filter, in input samples.
+    let half_width = ZERO_CROSSINGS as f64 / cutoff;
+    let table = filter_table();
+
+    let output_len = (samples.len() as f64 * ratio).ceil() as usize;
+    let mut output = Vec::with_capacity(output_len);
+    for n in 0..output_len {
+        // Position of this output sample in the input.
+        let position = n as f64 / ratio;
+        let first = (position - half_width).ceil().max(0.0) as usize;
+        let last = ((position + half_width).floor() as usize).min(samples.len() - 1);
+
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+        for (i, sample) in samples.iter().enumerate().take(last + 1).skip(first) {
+            // Distance from the filter's center, in zero crossings.
+            let distance = ((position - i as f64) * cutoff).abs();
+            let weight = lookup(&table, distance);
+            sum += *sample as f64 * weight;
+            weights += weight;
+        }
+        // Normalizing by the sum of the weights keeps unity gain, including at
+        // the edges where the filter is truncated.
+        let value = if weights != 0.0 { sum / weights } else { 0.0 };
+        output.push(value.round().max(i16::MIN as f64).min(i16::MAX as f64) as i16);
+    }
+    output
+}
+
+// Precompute one side of the windowed sinc filter, at RESOLUTION points per
+// zero crossing.
+fn filter_table() -> Vec<f64> {
+    let len = ZERO_CROSSINGS * RESOLUTION + 1;
+    (0..len)
+        .map(|i| {
+            let x = i as f64 / RESOLUTION as f64;
+            let sinc = if i == 0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            // Blackman window, from 1 at the center to 0 at the edge.
+            let u = x / ZERO_CROSSINGS as f64;
+            let window = 0.42 + 0.5 * (PI * u).cos() + 0.08 * (2.0 * PI * u).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+// Linearly interpolate the filter at a distance (in zero crossings) from its center.
+fn lookup(table: &[f64], distance: f64) -> f64 {
+    let index = distance * RESOLUTION as f64;
+    let i = index.floor() as usize;
+    if i + 1 >= table.len() {
+        return 0.0;
+    }
+    let fraction = index - i as f64;
+    table[i] + (table[i + 1] - table[i]) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sinc_of(len: usize, value: i16, from_hz: u32, to_hz: u32) -> Vec<i16> {
+        resample(vec![value; len], from_hz, to_hz, Interpolation::Sinc)
+    }
+
+    #[test]
+    fn output_length_follows_rate_ratio() {
+        assert_eq!(sinc_of(8000, 0, 8000, 16000).len(), 16000);
+        assert_eq!(sinc_of(48000, 0, 48000, 16000).len(), 16000);
+        // 44100 / 16000 doesn't divide, a partial sample is kept.
+        assert_eq!(sinc_of(4411, 0, 44100, 16000).len(), 1601);
+    }
+
+    #[test]
+    fn dc_keeps_unity_gain() {
+        for &from_hz in &[8000, 48000] {
+            let output = sinc_of(from_hz as usize / 10, 10000, from_hz, 16000);
+            assert!(output.iter().all(|s| (s - 10000).abs() <= 1), "{} Hz", from_hz);
+        }
+    }
+
+    #[test]
+    fn same_rate_unchanged() {
+        let samples = vec![1, -2, 3, -4];
+        assert_eq!(resample(samples.clone(), 16000, 16000, Interpolation::Sinc), samples);
+    }
+}
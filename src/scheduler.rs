@@ -0,0 +1,594 @@
+// Timers, alarms and reminders kept by the server, so they go off whether or
+// not a client is connected. Alarms and reminders are kept as timers ending at
+// the time they're due, told apart by their kind. The schedule is saved to a
+// JSON file after every change, and changes are pushed to clients subscribed
+// to /timers/events as Server-Sent Events. Recent events are kept, so a client
+// reconnecting with Last-Event-ID receives what it missed, including timers
+// that ended while the server was down.
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time;
+
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+// Events kept for clients that reconnect.
+const MAX_EVENTS: usize = 100;
+// How often a comment is sent to idle clients, in milliseconds, so
+// disconnected clients are noticed and forgotten.
+const KEEPALIVE: i64 = 15000;
+// Longest wait between checks for timers that have ended, in milliseconds.
+const MAX_WAIT: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Timer,
+    Alarm,
+    Reminder,
+}
+
+// Schedules saved before alarms existed only have timers.
+impl Default for Kind {
+    fn default() -> Self {
+        Kind::Timer
+    }
+}
+
+// Times are in milliseconds since the Unix epoch, durations in milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Timer {
+    id: u64,
+    #[serde(default)]
+    kind: Kind,
+    // The name of a timer, or what to be reminded of.
+    name: Option<String>,
+    // Length of the timer when it was set.
+    length: i64,
+    state: TimerState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum TimerState {
+    Running { end: i64 },
+    Paused { remaining: i64 },
+}
+
+// A timer as seen by clients, with times in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerStatus {
+    pub id: u64,
+    #[serde(default)]
+    pub kind: Kind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Length of the timer when it was set.
+    pub seconds: f64,
+    pub remaining: f64,
+    // When the timer ends, in the configured timezone, unless it's paused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    pub paused: bool,
+}
+
+// A change to a timer: "set", "cancelled", "paused", "resumed" or "expired",
+// which is when alarms and reminders are due.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Event {
+    id: u64,
+    event: String,
+    timer: TimerStatus,
+}
+
+// Everything saved across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Schedule {
+    next_id: u64,
+    next_event: u64,
+    timers: Vec<Timer>,
+    events: VecDeque<Event>,
+}
+
+struct State {
+    schedule: Schedule,
+    subscribers: Vec<UnboundedSender<Bytes>>,
+    // When the last keepalive was sent.
+    keepalive: i64,
+}
+
+pub struct Scheduler {
+    path: PathBuf,
+    timezone: Tz,
+    state: Mutex<State>,
+}
+
+impl Scheduler {
+    // Load the schedule saved at path, starting empty if there is none.
+    pub fn load(path: &Path, timezone: Tz) -> Result<Self, String> {
+        let schedule = match std::fs::read_to_string(path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(s) => s,
+                Err(e) => return Err(format!("failed to parse the schedule {:?}: {}", path, e)),
+            },
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Schedule::default(),
+            Err(e) => return Err(format!("failed to read the schedule {:?}: {}", path, e)),
+        };
+        Ok(Scheduler {
+            path: path.to_path_buf(),
+            timezone,
+            state: Mutex::new(State {
+                schedule,
+                subscribers: Vec::new(),
+                keepalive: 0,
+            }),
+        })
+    }
+
+    // Start a timer, optionally named so it can be told apart from others, or
+    // set an alarm or reminder.
+    pub fn add(
+        &self,
+        kind: Kind,
+        name: Option<&str>,
+        now: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<TimerStatus, String> {
+        let mut state = self.lock();
+        if let (Kind::Timer, Some(name)) = (kind, name) {
+            if state.schedule.timers.iter().any(|t| t.kind == kind && is_named(t, name)) {
+                return Err(format!("there is already a timer named '{}'", name));
+            }
+        }
+        state.schedule.next_id += 1;
+        let timer = Timer {
+            id: state.schedule.next_id,
+            kind,
+            name: name.map(|n| n.to_string()),
+            length: end.signed_duration_since(now).num_milliseconds(),
+            state: TimerState::Running {
+                end: end.timestamp_millis(),
+            },
+        };
+        state.schedule.timers.push(timer.clone());
+        Ok(self.changed(&mut state, "set", &timer, now))
+    }
+
+    // Everything scheduled, the first to end first.
+    pub fn list(&self, now: DateTime<Utc>) -> Vec<TimerStatus> {
+        let state = self.lock();
+        let mut timers: Vec<TimerStatus> = state
+            .schedule
+            .timers
+            .iter()
+            .map(|t| t.status(now.timestamp_millis(), &self.timezone))
+            .collect();
+        timers.sort_by(|a, b| a.remaining.partial_cmp(&b.remaining).unwrap_or(std::cmp::Ordering::Equal));
+        timers
+    }
+
+    pub fn cancel(&self, name: Option<&str>, now: DateTime<Utc>) -> Result<TimerStatus, String> {
+        let mut state = self.lock();
+        let index = select(&state.schedule.timers, name, "timer", |t| t.kind == Kind::Timer)?;
+        let timer = state.schedule.timers.remove(index);
+        Ok(self.changed(&mut state, "cancelled", &timer, now))
+    }
+
+    // Delete an alarm or reminder: the one named, or due within the window
+    // (from and to, in milliseconds since the epoch), or the only one.
+    pub fn delete(
+        &self,
+        name: Option<&str>,
+        window: Option<(i64, i64)>,
+        now: DateTime<Utc>,
+    ) -> Result<TimerStatus, String> {
+        let mut state = self.lock();
+        let index = select(&state.schedule.timers, name, "alarm or reminder", |t| {
+            let due = match (t.state.end(), window) {
+                (Some(end), Some((from, to))) => end >= from && end < to,
+                (_, None) => true,
+                (None, Some(_)) => false,
+            };
+            t.kind != Kind::Timer && due
+        })?;
+        let timer = state.schedule.timers.remove(index);
+        Ok(self.changed(&mut state, "cancelled", &timer, now))
+    }
+
+    pub fn pause(&self, name: Option<&str>, now: DateTime<Utc>) -> Result<TimerStatus, String> {
+        let mut state = self.lock();
+        let index = select(&state.schedule.timers, name, "running timer", |t| {
+            t.kind == Kind::Timer && t.state.end().is_some()
+        })?;
+        let timer = &mut state.schedule.timers[index];
+        if let TimerState::Running { end } = timer.state {
+            timer.state = TimerState::Paused {
+                remaining: (end - now.timestamp_millis()).max(0),
+            };
+        }
+        let timer = timer.clone();
+        Ok(self.changed(&mut state, "paused", &timer, now))
+    }
+
+    pub fn resume(&self, name: Option<&str>, now: DateTime<Utc>) -> Result<TimerStatus, String> {
+        let mut state = self.lock();
+        let index = select(&state.schedule.timers, name, "paused timer", |t| {
+            t.kind == Kind::Timer && t.state.end().is_none()
+        })?;
+        let timer = &mut state.schedule.timers[index];
+        if let TimerState::Paused { remaining } = timer.state {
+            timer.state = TimerState::Running {
+                end: now.timestamp_millis() + remaining,
+            };
+        }
+        let timer = timer.clone();
+        Ok(self.changed(&mut state, "resumed", &timer, now))
+    }
+
+    // A new client, receiving every event after last_event and then every
+    // future event.
+    pub fn subscribe(&self, last_event: Option<u64>) -> UnboundedReceiver<Bytes> {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut state = self.lock();
+        // Sent right away, so the client knows it's connected.
+        let _ = sender.unbounded_send(Bytes::from_static(b": connected\n\n"));
+        if let Some(last_event) = last_event {
+            for event in state.schedule.events.iter().filter(|e| e.id > last_event) {
+                let _ = sender.unbounded_send(event.frame());
+            }
+        }
+        state.subscribers.push(sender);
+        receiver
+    }
+
+    // Remove the timers that have ended, returning how long to wait before
+    // checking again.
+    fn tick(&self, now: DateTime<Utc>) -> time::Duration {
+        let mut state = self.lock();
+        let now_ms = now.timestamp_millis();
+        let (expired, timers): (Vec<Timer>, Vec<Timer>) = state
+            .schedule
+            .timers
+            .drain(..)
+            .partition(|t| t.state.end().map_or(false, |end| end <= now_ms));
+        state.schedule.timers = timers;
+        for timer in &expired {
+            self.changed(&mut state, "expired", timer, now);
+        }
+        if now_ms - state.keepalive >= KEEPALIVE {
+            state.keepalive = now_ms;
+            state.send(Bytes::from_static(b": keepalive\n\n"));
+        }
+        let next = state
+            .schedule
+            .timers
+            .iter()
+            .filter_map(|t| t.state.end().map(|end| end - now_ms))
+            .min()
+            .unwrap_or(MAX_WAIT);
+        time::Duration::from_millis(next.max(1).min(MAX_WAIT) as u64)
+    }
+
+    // Record and push a change to a timer, and save the schedule.
+    fn changed(&self, state: &mut State, event: &str, timer: &Timer, now: DateTime<Utc>) -> TimerStatus {
+        let status = timer.status(now.timestamp_millis(), &self.timezone);
+        state.schedule.next_event += 1;
+        let event = Event {
+            id: state.schedule.next_event,
+            event: event.to_string(),
+            timer: status.clone(),
+        };
+        state.send(event.frame());
+        state.schedule.events.push_back(event);
+        while state.schedule.events.len() > MAX_EVENTS {
+            state.schedule.events.pop_front();
+        }
+        if let Err(e) = self.save(&state.schedule) {
+            eprintln!("ERROR, {}", e);
+        }
+        status
+    }
+
+    // The schedule is written to a temporary file first, so a crash never
+    // leaves it truncated.
+    fn save(&self, schedule: &Schedule) -> Result<(), String> {
+        let json = match serde_json::to_string_pretty(schedule) {
+            Ok(j) => j,
+            Err(e) => return Err(format!("failed to serialize the schedule: {}", e)),
+        };
+        let temporary = self.path.with_extension("tmp");
+        if let Err(e) = std::fs::write(&temporary, json) {
+            return Err(format!("failed to write the schedule {:?}: {}", temporary, e));
+        }
+        if let Err(e) = std::fs::rename(&temporary, &self.path) {
+            return Err(format!("failed to replace the schedule {:?}: {}", self.path, e));
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl State {
+    // Push to every client, forgetting those that have disconnected.
+    fn send(&mut self, bytes: Bytes) {
+        self.subscribers.retain(|s| s.unbounded_send(bytes.clone()).is_ok());
+    }
+}
+
+impl TimerState {
+    // When the timer ends, unless it's paused.
+    fn end(&self) -> Option<i64> {
+        match self {
+            TimerState::Running { end } => Some(*end),
+            TimerState::Paused { .. } => None,
+        }
+    }
+}
+
+impl Timer {
+    fn status(&self, now: i64, timezone: &Tz) -> TimerStatus {
+        let (remaining, end) = match self.state {
+            TimerState::Running { end } => ((end - now).max(0), Some(timezone.timestamp_millis(end).to_rfc3339())),
+            TimerState::Paused { remaining } => (remaining, None),
+        };
+        TimerStatus {
+            id: self.id,
+            kind: self.kind,
+            name: self.name.clone(),
+            seconds: self.length as f64 / 1000.0,
+            remaining: remaining as f64 / 1000.0,
+            paused: end.is_none(),
+            end,
+        }
+    }
+}
+
+impl Event {
+    fn frame(&self) -> Bytes {
+        let data = serde_json::to_string(self).unwrap_or_default();
+        Bytes::from(format!("id: {}\nevent: {}\ndata: {}\n\n", self.id, self.event, data))
+    }
+}
+
+fn is_named(timer: &Timer, name: &str) -> bool {
+    timer.name.as_ref().map_or(false, |n| n.eq_ignore_ascii_case(name))
+}
+
+// The timer a command refers to: the one with the spoken name, or the only
+// one matching if no name was spoken. The noun describes what's looked for,
+// such as "paused timer".
+fn select(timers: &[Timer], name: Option<&str>, noun: &str, matches: impl Fn(&Timer) -> bool) -> Result<usize, String> {
+    let candidates: Vec<usize> = timers
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| matches(t) && name.map_or(true, |n| is_named(t, n)))
+        .map(|(i, _)| i)
+        .collect();
+    match (candidates.len(), name) {
+        (1, _) => Ok(candidates[0]),
+        (0, Some(name)) => Err(format!("there is no {} named '{}'", noun, name)),
+        (0, None) => Err(format!("there is no {}", noun)),
+        (count, _) => Err(format!("there are {} of them, say which {} you mean", count, noun)),
+    }
+}
+
+// Spell out a number of seconds, such as "1 hour 5 minutes".
+pub fn human_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as i64;
+    let parts: Vec<String> = [(seconds / 3600, "hour"), (seconds / 60 % 60, "minute"), (seconds % 60, "second")]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{} {}{}", count, unit, if *count == 1 { "" } else { "s" }))
+        .collect();
+    if parts.is_empty() {
+        return "0 seconds".to_string();
+    }
+    parts.join(" ")
+}
+
+// End timers in the background, as they expire.
+pub fn run(scheduler: web::Data<Scheduler>) {
+    thread::spawn(move || loop {
+        let wait = scheduler.tick(Utc::now());
+        thread::sleep(wait);
+    });
+}
+
+pub async fn _timers(scheduler: web::Data<Scheduler>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "timers": scheduler.list(Utc::now()) }))
+}
+
+// Push changes to timers as Server-Sent Events.
+pub async fn _timer_events(req: HttpRequest, scheduler: web::Data<Scheduler>) -> HttpResponse {
+    let last_event = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+    let receiver = scheduler.subscribe(last_event);
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(receiver.map(Ok::<_, actix_web::Error>))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Some time in September 2020.
+    const T0: i64 = 1_600_000_000_000;
+
+    fn at(ms: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis(T0 + ms)
+    }
+
+    // A scheduler saving to a file of its own, so tests can run in parallel.
+    fn scheduler(test: &str) -> Scheduler {
+        let path = std::env::temp_dir().join(format!("kakaia-{}-{}.json", test, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Scheduler::load(&path, Tz::UTC).unwrap()
+    }
+
+    fn timer(id: u64, kind: Kind, name: Option<&str>, state: TimerState) -> Timer {
+        Timer {
+            id,
+            kind,
+            name: name.map(|n| n.to_string()),
+            length: 60000,
+            state,
+        }
+    }
+
+    fn frames(receiver: &mut UnboundedReceiver<Bytes>) -> Vec<String> {
+        let mut frames = Vec::new();
+        while let Ok(Some(frame)) = receiver.try_next() {
+            frames.push(String::from_utf8(frame.to_vec()).unwrap());
+        }
+        frames
+    }
+
+    #[test]
+    fn select_by_name_or_only_match() {
+        let timers = vec![
+            timer(1, Kind::Timer, Some("pasta"), TimerState::Running { end: T0 }),
+            timer(2, Kind::Timer, Some("eggs"), TimerState::Paused { remaining: 1000 }),
+            timer(3, Kind::Alarm, None, TimerState::Running { end: T0 }),
+        ];
+        let is_timer = |t: &Timer| t.kind == Kind::Timer;
+        assert_eq!(select(&timers, Some("Pasta"), "timer", is_timer), Ok(0));
+        assert_eq!(select(&timers, Some("eggs"), "timer", is_timer), Ok(1));
+        assert_eq!(
+            select(&timers, None, "timer", is_timer),
+            Err("there are 2 of them, say which timer you mean".to_string())
+        );
+        assert_eq!(
+            select(&timers, Some("rice"), "timer", is_timer),
+            Err("there is no timer named 'rice'".to_string())
+        );
+        assert_eq!(select(&timers, None, "paused timer", |t| t.state.end().is_none()), Ok(1));
+        assert_eq!(select(&timers, None, "alarm", |t| t.kind == Kind::Alarm), Ok(2));
+        assert_eq!(
+            select(&timers, None, "reminder", |t| t.kind == Kind::Reminder),
+            Err("there is no reminder".to_string())
+        );
+    }
+
+    #[test]
+    fn pause_and_resume_keep_the_time_left() {
+        let scheduler = scheduler("pause");
+        scheduler.add(Kind::Timer, Some("pasta"), at(0), at(60000)).unwrap();
+
+        let paused = scheduler.pause(None, at(20000)).unwrap();
+        assert!(paused.paused);
+        assert_eq!(paused.remaining, 40.0);
+        assert_eq!(paused.end, None);
+        // Time doesn't run while paused.
+        assert_eq!(scheduler.list(at(50000))[0].remaining, 40.0);
+        assert_eq!(scheduler.pause(None, at(50000)).unwrap_err(), "there is no running timer");
+
+        let resumed = scheduler.resume(Some("pasta"), at(100000)).unwrap();
+        assert!(!resumed.paused);
+        assert_eq!(resumed.remaining, 40.0);
+        assert_eq!(resumed.seconds, 60.0);
+        assert_eq!(resumed.end, Some(at(140000).to_rfc3339()));
+        assert_eq!(scheduler.list(at(130000))[0].remaining, 10.0);
+        assert_eq!(scheduler.resume(None, at(130000)).unwrap_err(), "there is no paused timer");
+        let _ = std::fs::remove_file(&scheduler.path);
+    }
+
+    #[test]
+    fn tick_expires_timers_and_waits_for_the_next() {
+        let scheduler = scheduler("tick");
+        assert_eq!(scheduler.tick(at(0)), time::Duration::from_millis(MAX_WAIT as u64));
+        scheduler.add(Kind::Timer, None, at(0), at(5000)).unwrap();
+        scheduler.add(Kind::Alarm, None, at(0), at(5300)).unwrap();
+        scheduler.add(Kind::Timer, Some("paused"), at(0), at(1000)).unwrap();
+        scheduler.pause(Some("paused"), at(0)).unwrap();
+        let mut receiver = scheduler.subscribe(None);
+
+        // Never waits longer than MAX_WAIT, nor past the next end.
+        assert_eq!(scheduler.tick(at(1000)), time::Duration::from_millis(MAX_WAIT as u64));
+        assert_eq!(scheduler.tick(at(4800)), time::Duration::from_millis(200));
+        assert_eq!(scheduler.list(at(4800)).len(), 3);
+
+        assert_eq!(scheduler.tick(at(5000)), time::Duration::from_millis(300));
+        let timers = scheduler.list(at(5000));
+        assert_eq!(timers.len(), 2);
+        assert!(timers.iter().all(|t| t.id != 1));
+        // Late ticks still expire everything due.
+        assert_eq!(scheduler.tick(at(9000)), time::Duration::from_millis(MAX_WAIT as u64));
+        let timers = scheduler.list(at(9000));
+        assert_eq!(timers.len(), 1);
+        assert!(timers[0].paused);
+
+        let expired: Vec<String> = frames(&mut receiver)
+            .into_iter()
+            .filter(|f| f.contains("event: expired"))
+            .collect();
+        assert_eq!(expired.len(), 2);
+        assert!(expired[0].contains("\"id\":1,\"kind\":\"timer\""));
+        assert!(expired[1].contains("\"id\":2,\"kind\":\"alarm\""));
+        let _ = std::fs::remove_file(&scheduler.path);
+    }
+
+    #[test]
+    fn subscribe_replays_events_after_last_event_id() {
+        let scheduler = scheduler("replay");
+        scheduler.add(Kind::Timer, Some("pasta"), at(0), at(60000)).unwrap();
+        scheduler.add(Kind::Timer, Some("eggs"), at(0), at(60000)).unwrap();
+        scheduler.cancel(Some("pasta"), at(1000)).unwrap();
+
+        let mut receiver = scheduler.subscribe(Some(1));
+        let replayed = frames(&mut receiver);
+        assert_eq!(replayed.len(), 3);
+        assert_eq!(replayed[0], ": connected\n\n");
+        assert!(replayed[1].starts_with("id: 2\nevent: set\ndata: "));
+        assert!(replayed[2].starts_with("id: 3\nevent: cancelled\ndata: "));
+        assert!(replayed[2].contains("\"name\":\"pasta\""));
+
+        // Without Last-Event-ID only new events are sent.
+        let mut receiver = scheduler.subscribe(None);
+        assert_eq!(frames(&mut receiver), vec![": connected\n\n".to_string()]);
+        scheduler.pause(None, at(2000)).unwrap();
+        let pushed = frames(&mut receiver);
+        assert_eq!(pushed.len(), 1);
+        assert!(pushed[0].starts_with("id: 4\nevent: paused\n"));
+        let _ = std::fs::remove_file(&scheduler.path);
+    }
+
+    #[test]
+    fn load_what_was_saved() {
+        let scheduler = scheduler("save");
+        scheduler.add(Kind::Timer, Some("pasta"), at(0), at(60000)).unwrap();
+        scheduler.add(Kind::Timer, None, at(0), at(30000)).unwrap();
+        scheduler.pause(None, at(10000)).unwrap_err();
+        scheduler.pause(Some("pasta"), at(10000)).unwrap();
+        scheduler.add(Kind::Reminder, Some("call mom"), at(0), at(90000)).unwrap();
+
+        let loaded = Scheduler::load(&scheduler.path, Tz::UTC).unwrap();
+        assert_eq!(
+            serde_json::to_string(&loaded.list(at(20000))).unwrap(),
+            serde_json::to_string(&scheduler.list(at(20000))).unwrap()
+        );
+        assert_eq!(loaded.lock().schedule.events.len(), 4);
+        // Ids carry on where they left off.
+        assert_eq!(loaded.add(Kind::Alarm, None, at(0), at(1000)).unwrap().id, 4);
+        assert!(!scheduler.path.with_extension("tmp").exists());
+
+        std::fs::write(&scheduler.path, "not json").unwrap();
+        assert!(Scheduler::load(&scheduler.path, Tz::UTC).is_err());
+        let _ = std::fs::remove_file(&scheduler.path);
+    }
+}
@@ -0,0 +1,462 @@
+use std::fmt;
+
+use chrono::{DateTime, Datelike, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use snips_nlu_lib::ontology::{self, IntentParserResult, Precision, SlotValue};
+
+// Why a slot couldn't be read from the parsed text.
+#[derive(Debug)]
+pub enum SlotError {
+    // The slot wasn't recognized in the text.
+    Missing(String),
+    // The slot was recognized, but its value isn't what the command expects.
+    Invalid { slot: String, expected: &'static str },
+}
+
+impl fmt::Display for SlotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SlotError::Missing(slot) => write!(f, "slot '{}' is missing", slot),
+            SlotError::Invalid { slot, expected } => write!(f, "slot '{}' is not {}", slot, expected),
+        }
+    }
+}
+
+// The value of a slot, converted from the Snips ontology. Every kind of value
+// is converted in Slot::from, so the compiler flags any kind added to the
+// ontology.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slot {
+    Number(f64),
+    Ordinal(i64),
+    Percentage(f64),
+    Temperature(Temperature),
+    Duration(Duration),
+    InstantTime(InstantTime),
+    TimeInterval { from: Option<String>, to: Option<String> },
+    AmountOfMoney { value: f64, unit: Option<String> },
+    // Custom entities, and built-in entities resolved to a name, such as cities.
+    Custom(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Temperature {
+    pub value: f64,
+    // "celsius", "fahrenheit", "kelvin" or "degree", if spoken.
+    pub unit: Option<String>,
+}
+
+// A duration in calendar units, which may be fractional, such as 1.5 hours.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duration {
+    pub years: f64,
+    pub quarters: f64,
+    pub months: f64,
+    pub weeks: f64,
+    pub days: f64,
+    pub hours: f64,
+    pub minutes: f64,
+    pub seconds: f64,
+    // False for approximations, such as "about an hour".
+    pub exact: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstantTime {
+    // Date and time with its offset, as resolved by the engine in the local
+    // timezone, which is set to --timezone at startup.
+    pub value: String,
+    pub grain: Grain,
+    pub exact: bool,
+}
+
+// The most precise unit spoken, "tomorrow" having a grain of Day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grain {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl From<&SlotValue> for Slot {
+    fn from(value: &SlotValue) -> Self {
+        match value {
+            SlotValue::Custom(v) => Slot::Custom(v.value.clone()),
+            SlotValue::Number(v) => Slot::Number(v.value),
+            SlotValue::Ordinal(v) => Slot::Ordinal(v.value),
+            SlotValue::Percentage(v) => Slot::Percentage(v.value),
+            SlotValue::InstantTime(v) => Slot::InstantTime(InstantTime {
+                value: v.value.clone(),
+                grain: Grain::from(&v.grain),
+                exact: is_exact(&v.precision),
+            }),
+            SlotValue::TimeInterval(v) => Slot::TimeInterval {
+                from: v.from.clone(),
+                to: v.to.clone(),
+            },
+            SlotValue::AmountOfMoney(v) => Slot::AmountOfMoney {
+                value: widen(v.value),
+                unit: v.unit.clone(),
+            },
+            SlotValue::Temperature(v) => Slot::Temperature(Temperature {
+                value: widen(v.value),
+                unit: v.unit.clone(),
+            }),
+            SlotValue::Duration(v) => Slot::Duration(Duration {
+                years: v.years as f64,
+                quarters: v.quarters as f64,
+                months: v.months as f64,
+                weeks: v.weeks as f64,
+                days: v.days as f64,
+                hours: v.hours as f64,
+                minutes: v.minutes as f64,
+                seconds: v.seconds as f64,
+                exact: is_exact(&v.precision),
+            }),
+            SlotValue::MusicAlbum(v)
+            | SlotValue::MusicArtist(v)
+            | SlotValue::MusicTrack(v)
+            | SlotValue::City(v)
+            | SlotValue::Country(v)
+            | SlotValue::Region(v) => Slot::Custom(v.value.clone()),
+        }
+    }
+}
+
+impl From<&ontology::Grain> for Grain {
+    fn from(grain: &ontology::Grain) -> Self {
+        match grain {
+            ontology::Grain::Year => Grain::Year,
+            ontology::Grain::Quarter => Grain::Quarter,
+            ontology::Grain::Month => Grain::Month,
+            ontology::Grain::Week => Grain::Week,
+            ontology::Grain::Day => Grain::Day,
+            ontology::Grain::Hour => Grain::Hour,
+            ontology::Grain::Minute => Grain::Minute,
+            ontology::Grain::Second => Grain::Second,
+        }
+    }
+}
+
+fn is_exact(precision: &Precision) -> bool {
+    match precision {
+        Precision::Exact => true,
+        Precision::Approximate => false,
+    }
+}
+
+// The ontology stores some values as f32, convert them to the f64 closest to
+// their decimal representation, so 98.6 stays 98.6.
+fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or_else(|_| f64::from(value))
+}
+
+// Longest durations resolved, well within the range of chrono.
+const MAX_MONTHS: f64 = 12.0 * 1000.0;
+const MAX_DAYS: f64 = 366.0 * 1000.0;
+const MAX_SECONDS: f64 = 86400.0 * 366.0 * 1000.0;
+
+impl Duration {
+    // The time the duration ends when starting now, or None if it's negative
+    // or absurdly long. Calendar units are added to the local date and time,
+    // so "in one month" lands on the same day next month (or the last day of
+    // a shorter month), and "in one day" at the same time of day across
+    // daylight saving changes. A fraction of a month is that fraction of the
+    // following month, and a fraction of a day is that fraction of 24 hours.
+    pub fn resolve<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let months = self.years * 12.0 + self.quarters * 3.0 + self.months;
+        let days = self.weeks * 7.0 + self.days;
+        let seconds = self.hours * 3600.0 + self.minutes * 60.0 + self.seconds;
+        for (value, max) in &[(months, MAX_MONTHS), (days, MAX_DAYS), (seconds, MAX_SECONDS)] {
+            if value.is_nan() || *value < 0.0 || *value > *max {
+                return None;
+            }
+        }
+
+        let mut local = add_months(now.naive_local(), months.trunc() as i32)?;
+        if months.fract() > 0.0 {
+            let month = add_months(local, 1)?.signed_duration_since(local);
+            local = local.checked_add_signed(fraction(month, months.fract()))?;
+        }
+        local = local.checked_add_signed(chrono::Duration::days(days.trunc() as i64))?;
+        local = local.checked_add_signed(fraction(chrono::Duration::days(1), days.fract()))?;
+
+        let end = localize(&now.timezone(), local)?;
+        end.checked_add_signed(fraction(chrono::Duration::seconds(1), seconds))
+    }
+}
+
+// Format of the instant times of the ontology, such as "2020-03-14 07:00:00 +00:00".
+const INSTANT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+impl InstantTime {
+    // The time in the given timezone. The value is an instant, so relative
+    // times such as "in 30 minutes" stay the same instant, while "7am" is 7am
+    // in the given timezone as long as the engine resolved it there.
+    pub fn resolve<Tz: TimeZone>(&self, timezone: &Tz) -> Option<DateTime<Tz>> {
+        match DateTime::parse_from_str(&self.value, INSTANT_TIME_FORMAT) {
+            Ok(t) => Some(t.with_timezone(timezone)),
+            Err(_) => None,
+        }
+    }
+}
+
+impl Grain {
+    // How long a time of this grain lasts, "tomorrow" lasting a day, or None
+    // for months and longer, which vary in length.
+    pub fn span(self) -> Option<chrono::Duration> {
+        match self {
+            Grain::Year | Grain::Quarter | Grain::Month => None,
+            Grain::Week => Some(chrono::Duration::weeks(1)),
+            Grain::Day => Some(chrono::Duration::days(1)),
+            Grain::Hour => Some(chrono::Duration::hours(1)),
+            Grain::Minute => Some(chrono::Duration::minutes(1)),
+            Grain::Second => Some(chrono::Duration::seconds(1)),
+        }
+    }
+}
+
+// A fraction of a chrono duration, to the millisecond.
+fn fraction(duration: chrono::Duration, fraction: f64) -> chrono::Duration {
+    chrono::Duration::milliseconds((duration.num_milliseconds() as f64 * fraction).round() as i64)
+}
+
+// Add calendar months, keeping the day of the month unless the month is
+// shorter, so January 31st plus one month is the last day of February.
+fn add_months(local: NaiveDateTime, months: i32) -> Option<NaiveDateTime> {
+    let total = local.year() * 12 + local.month0() as i32 + months;
+    let year = total.div_euclid(12);
+    let month = total.rem_euclid(12) as u32 + 1;
+    // Every month has at least 28 days.
+    let day = (29..=local.day())
+        .rev()
+        .find(|day| NaiveDate::from_ymd_opt(year, month, *day).is_some())
+        .unwrap_or_else(|| local.day().min(28));
+    NaiveDate::from_ymd_opt(year, month, day).map(|date| date.and_time(local.time()))
+}
+
+// The zoned time of a local time, taking the earlier of repeated times when
+// clocks go back, and moving skipped times forward by the length of the gap
+// when clocks go forward, so 2:15 becomes 2:45 where clocks skip from 2:00 to
+// 2:30.
+fn localize<Tz: TimeZone>(timezone: &Tz, local: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match timezone.from_local_datetime(&local) {
+        LocalResult::Single(t) => Some(t),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest),
+        LocalResult::None => {
+            // Read the time with the offset in use before the gap, a day
+            // earlier being before it whatever the offset.
+            let before = local.checked_sub_signed(chrono::Duration::days(1))?;
+            let offset = timezone.offset_from_utc_datetime(&before).fix().local_minus_utc();
+            let utc = local.checked_sub_signed(chrono::Duration::seconds(i64::from(offset)))?;
+            Some(timezone.from_utc_datetime(&utc))
+        }
+    }
+}
+
+// The typed slots recognized in the text, found by entity and slot name.
+pub struct Slots {
+    slots: Vec<(String, String, Slot)>,
+}
+
+impl Slots {
+    pub fn new(parsed: &IntentParserResult) -> Self {
+        Slots {
+            slots: parsed
+                .slots
+                .iter()
+                .map(|s| (s.entity.clone(), s.slot_name.clone(), Slot::from(&s.value)))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, entity: &str, slot_name: &str) -> Result<&Slot, SlotError> {
+        match self
+            .slots
+            .iter()
+            .find(|(e, s, _)| e == entity && s == slot_name)
+        {
+            Some((_, _, slot)) => Ok(slot),
+            None => Err(SlotError::Missing(slot_name.to_string())),
+        }
+    }
+
+    // Whether an optional slot was recognized.
+    pub fn has(&self, entity: &str, slot_name: &str) -> bool {
+        self.get(entity, slot_name).is_ok()
+    }
+
+    pub fn number(&self, entity: &str, slot_name: &str) -> Result<f64, SlotError> {
+        match self.get(entity, slot_name)? {
+            Slot::Number(n) if n.is_finite() => Ok(*n),
+            _ => Err(invalid(slot_name, "a number")),
+        }
+    }
+
+    pub fn temperature(&self, entity: &str, slot_name: &str) -> Result<&Temperature, SlotError> {
+        match self.get(entity, slot_name)? {
+            Slot::Temperature(t) if t.value.is_finite() => Ok(t),
+            _ => Err(invalid(slot_name, "a temperature")),
+        }
+    }
+
+    pub fn duration(&self, entity: &str, slot_name: &str) -> Result<&Duration, SlotError> {
+        match self.get(entity, slot_name)? {
+            Slot::Duration(d) => Ok(d),
+            _ => Err(invalid(slot_name, "a duration")),
+        }
+    }
+
+    pub fn instant_time(&self, entity: &str, slot_name: &str) -> Result<&InstantTime, SlotError> {
+        match self.get(entity, slot_name)? {
+            Slot::InstantTime(t) => Ok(t),
+            _ => Err(invalid(slot_name, "a time")),
+        }
+    }
+
+    pub fn custom(&self, entity: &str, slot_name: &str) -> Result<&str, SlotError> {
+        match self.get(entity, slot_name)? {
+            Slot::Custom(c) => Ok(c),
+            _ => Err(invalid(slot_name, "text")),
+        }
+    }
+}
+
+fn invalid(slot_name: &str, expected: &'static str) -> SlotError {
+    SlotError::Invalid {
+        slot: slot_name.to_string(),
+        expected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use chrono_tz::Australia::Lord_Howe;
+    use chrono_tz::Europe::Rome;
+
+    fn duration(months: f64, days: f64, seconds: f64) -> Duration {
+        Duration {
+            years: 0.0,
+            quarters: 0.0,
+            months,
+            weeks: 0.0,
+            days,
+            hours: 0.0,
+            minutes: 0.0,
+            seconds,
+            exact: true,
+        }
+    }
+
+    fn naive(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn add_months_keeps_the_day_or_clamps_it() {
+        assert_eq!(add_months(naive(2020, 1, 31, 9, 0), 1), Some(naive(2020, 2, 29, 9, 0)));
+        assert_eq!(add_months(naive(2021, 1, 31, 9, 0), 1), Some(naive(2021, 2, 28, 9, 0)));
+        assert_eq!(add_months(naive(2020, 1, 31, 9, 0), 3), Some(naive(2020, 4, 30, 9, 0)));
+        assert_eq!(add_months(naive(2020, 11, 15, 9, 0), 14), Some(naive(2022, 1, 15, 9, 0)));
+        assert_eq!(add_months(naive(2020, 3, 31, 9, 0), -1), Some(naive(2020, 2, 29, 9, 0)));
+    }
+
+    #[test]
+    fn resolve_calendar_months() {
+        let now = Utc.ymd(2020, 1, 31).and_hms(10, 0, 0);
+        let end = duration(1.0, 0.0, 0.0).resolve(&now).unwrap();
+        assert_eq!(end, Utc.ymd(2020, 2, 29).and_hms(10, 0, 0));
+    }
+
+    #[test]
+    fn resolve_fractions_of_months_and_days() {
+        // Half of March, which follows the first month, is 15.5 days.
+        let now = Utc.ymd(2020, 2, 1).and_hms(0, 0, 0);
+        let end = duration(1.5, 0.0, 0.0).resolve(&now).unwrap();
+        assert_eq!(end, Utc.ymd(2020, 3, 16).and_hms(12, 0, 0));
+
+        let now = Utc.ymd(2020, 3, 14).and_hms(10, 0, 0);
+        let end = duration(0.0, 1.5, 0.0).resolve(&now).unwrap();
+        assert_eq!(end, Utc.ymd(2020, 3, 15).and_hms(22, 0, 0));
+        let end = duration(0.0, 0.0, 1.25).resolve(&now).unwrap();
+        assert_eq!(end, Utc.ymd(2020, 3, 14).and_hms_milli(10, 0, 1, 250));
+    }
+
+    #[test]
+    fn resolve_days_across_daylight_saving() {
+        // Clocks go forward at 2:00 on March 29th 2020 in Rome, so a day is
+        // 23 hours, but an hour is still an hour.
+        let now = Rome.ymd(2020, 3, 28).and_hms(12, 0, 0);
+        let end = duration(0.0, 1.0, 0.0).resolve(&now).unwrap();
+        assert_eq!(end.to_rfc3339(), "2020-03-29T12:00:00+02:00");
+        assert_eq!(end.signed_duration_since(now), chrono::Duration::hours(23));
+
+        let now = Rome.ymd(2020, 3, 29).and_hms(1, 30, 0);
+        let end = duration(0.0, 0.0, 3600.0).resolve(&now).unwrap();
+        assert_eq!(end.to_rfc3339(), "2020-03-29T03:30:00+02:00");
+    }
+
+    #[test]
+    fn resolve_into_a_gap_or_an_overlap() {
+        // 2:30 is skipped in Rome on March 29th 2020, and repeated on October
+        // 25th 2020.
+        let now = Rome.ymd(2020, 3, 28).and_hms(2, 30, 0);
+        let end = duration(0.0, 1.0, 0.0).resolve(&now).unwrap();
+        assert_eq!(end.to_rfc3339(), "2020-03-29T03:30:00+02:00");
+
+        let now = Rome.ymd(2020, 10, 24).and_hms(2, 30, 0);
+        let end = duration(0.0, 1.0, 0.0).resolve(&now).unwrap();
+        assert_eq!(end.to_rfc3339(), "2020-10-25T02:30:00+02:00");
+    }
+
+    #[test]
+    fn localize_moves_past_a_half_hour_gap() {
+        // Lord Howe Island goes from +10:30 to +11:00 at 2:00 on October 4th
+        // 2020, skipping to 2:30.
+        let end = localize(&Lord_Howe, naive(2020, 10, 4, 2, 15)).unwrap();
+        assert_eq!(end.to_rfc3339(), "2020-10-04T02:45:00+11:00");
+        let end = localize(&Rome, naive(2020, 3, 29, 2, 30)).unwrap();
+        assert_eq!(end.to_rfc3339(), "2020-03-29T03:30:00+02:00");
+        let end = localize(&Rome, naive(2020, 10, 25, 2, 30)).unwrap();
+        assert_eq!(end.to_rfc3339(), "2020-10-25T02:30:00+02:00");
+    }
+
+    fn instant_time(value: &str) -> InstantTime {
+        InstantTime {
+            value: value.to_string(),
+            grain: Grain::Minute,
+            exact: true,
+        }
+    }
+
+    #[test]
+    fn resolve_instant_times_as_instants() {
+        // "7am" resolved by the engine in Rome stays 7am there.
+        let time = instant_time("2020-03-14 07:00:00 +01:00").resolve(&Rome).unwrap();
+        assert_eq!(time.to_rfc3339(), "2020-03-14T07:00:00+01:00");
+        // "in 30 minutes" resolved on a UTC clock is the same instant in Rome,
+        // not 30 minutes after the same time of day there.
+        let time = instant_time("2020-03-14 10:30:00 +00:00").resolve(&Rome).unwrap();
+        assert_eq!(time.to_rfc3339(), "2020-03-14T11:30:00+01:00");
+        assert_eq!(time, Utc.ymd(2020, 3, 14).and_hms(10, 30, 0));
+        assert_eq!(instant_time("tomorrow").resolve(&Rome), None);
+    }
+
+    #[test]
+    fn resolve_rejects_negative_or_oversized_durations() {
+        let now = Utc.ymd(2020, 3, 14).and_hms(10, 0, 0);
+        assert_eq!(duration(0.0, -1.0, 0.0).resolve(&now), None);
+        assert_eq!(duration(0.0, 0.0, -0.5).resolve(&now), None);
+        assert_eq!(duration(MAX_MONTHS + 1.0, 0.0, 0.0).resolve(&now), None);
+        assert_eq!(duration(0.0, MAX_DAYS + 1.0, 0.0).resolve(&now), None);
+        assert_eq!(duration(0.0, 0.0, MAX_SECONDS * 2.0).resolve(&now), None);
+        assert_eq!(duration(0.0, f64::NAN, 0.0).resolve(&now), None);
+        assert!(duration(MAX_MONTHS, MAX_DAYS, MAX_SECONDS).resolve(&now).is_some());
+    }
+}
@@ -1,502 +1,418 @@
-use std::env;
-use std::io::Write;
-use std::path::Path;
-use std::sync::Mutex;
-
-use actix_web::{web, HttpResponse};
-use audrey::read::Reader;
-use audrey::sample::interpolate::{Converter, Linear};
-use audrey::sample::signal::{from_iter, Signal};
+use std::io::{Cursor, Read, Seek, Write};
+
+use actix_web::error::BlockingError;
+use actix_web::{web, HttpRequest, HttpResponse};
 use chrono::{DateTime, Utc};
+#[cfg(feature = "deepspeech")]
 use deepspeech::Model;
-use tempfile::NamedTempFile;
 use serde::Serialize;
 
+use crate::audio::{self, Audio, AudioDescription};
+use crate::codec::RawPcm;
+use crate::command::{self, KakaiaResponse, Registry};
+use crate::error::KakaiaError;
+use crate::fixture::FixtureSpeech;
 use crate::nlu::NLU;
+use crate::pool::Pool;
+use crate::upload;
 use crate::Configuration;
 
-// These constants are taken from the C++ sources of the client.
-const BEAM_WIDTH: u16 = 500;
-const LM_WEIGHT: f32 = 0.75;
-const VALID_WORD_COUNT_WEIGHT: f32 = 1.85;
-// The provided model was trained on this specific sample rate.
-const SAMPLE_RATE: u32 = 16_000;
+// DeepSpeech emits a character every 20 milliseconds.
+#[cfg(feature = "deepspeech")]
+const TIMESTEP: f64 = 0.02;
 
 #[derive(Debug)]
 pub struct AudioAsText {
     pub raw: String,
     pub filetype: String,
+    pub audio: Option<AudioDescription>,
+    pub words: Vec<WordTiming>,
+    pub confidence: Option<f64>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct KakaiaResponse {
-    command: String,
-    human: String,
-    raw: String,
-    result: f64,
+// When a word was spoken, in seconds from the start of the audio.
+#[derive(Debug, Clone, Serialize)]
+pub struct WordTiming {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
 }
 
+// Text recognized by a speech-to-text backend, with word timings and overall
+// confidence if the backend provides them.
 #[derive(Debug)]
-pub enum KakaiaCommandType {
-    None,
-    SetTimer,
-    ConvertTemperature,
-    SimpleCalculation,
+pub struct Recognition {
+    pub text: String,
+    pub words: Vec<WordTiming>,
+    pub confidence: Option<f64>,
 }
 
-#[derive(Debug)]
-pub struct KakaiaCommand {
-    command: KakaiaCommandType,
-    string: String,
-}
+// A speech-to-text backend, converting 16 kHz mono samples to text.
+pub trait SpeechToText: Send {
+    fn speech_to_text(&mut self, audio_buffer: &[i16]) -> Result<String, String>;
 
-impl KakaiaCommand {
-    pub fn from_str(command_str: &str) -> KakaiaCommand {
-        let command_type = match command_str {
-            "setTimer" => KakaiaCommandType::SetTimer,
-            "convertTemperature" => KakaiaCommandType::ConvertTemperature,
-            "simpleCalculation" => KakaiaCommandType::SimpleCalculation,
-            _ => KakaiaCommandType::None,
-        };
-        KakaiaCommand {
-            command: command_type,
-            string: command_str.to_string(),
-        }
+    fn speech_to_text_with_metadata(&mut self, audio_buffer: &[i16]) -> Result<Recognition, String> {
+        Ok(Recognition {
+            text: self.speech_to_text(audio_buffer)?,
+            words: Vec::new(),
+            confidence: None,
+        })
     }
 
-    pub fn to_string(command_type: KakaiaCommandType) -> String {
-        match command_type {
-            KakaiaCommandType::None => "none".to_string(),
-            KakaiaCommandType::SetTimer => "setTimer".to_string(),
-            KakaiaCommandType::ConvertTemperature => "convertTemperature".to_string(),
-            KakaiaCommandType::SimpleCalculation => "simpleCalculation".to_string(),
-        }
+    // Start a streaming recognition session, which borrows the backend until
+    // it is finished.
+    fn create_stream(&mut self) -> Result<Box<dyn SpeechStream + '_>, String>;
+}
+
+// Decode an audio file from any seekable source, such as an in-memory cursor,
+// and trim the silence around speech.
+pub fn decode_audio<R: Read + Seek>(
+    audio_file: R,
+    raw: Option<RawPcm>,
+    config: &Configuration,
+) -> Result<Audio, KakaiaError> {
+    let mut audio = audio::decode(audio_file, raw, config)?;
+    if !config.no_vad {
+        audio.trim_silence(config.vad_margin);
     }
+    Ok(audio)
 }
 
-impl KakaiaResponse {
-    pub fn new(command: &str, human: &str, raw: &str, result: f64) -> Self {
-        KakaiaResponse {
-            command: command.to_string(),
-            human: human.to_string(),
-            raw: raw.to_string(),
-            result: result,
+// Convert decoded audio to text, checking a recognizer out of the pool only
+// for the recognition itself.
+pub fn convert_audio_to_text(
+    speech_pool: &Pool<Box<dyn SpeechToText>>,
+    audio: Audio,
+) -> Result<AudioAsText, KakaiaError> {
+    // Don't run the model on silence, which only produces junk words.
+    let recognition = if audio.samples.is_empty() {
+        Recognition {
+            text: "".to_string(),
+            words: Vec::new(),
+            confidence: None,
         }
-    }
+    } else {
+        let mut speech = speech_pool.checkout()?;
+        match speech.speech_to_text_with_metadata(audio.samples.as_slice()) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(KakaiaError::Recognition(format!(
+                    "failed to convert audio to text: {}",
+                    e
+                )))
+            }
+        }
+    };
 
-    pub fn to_json_string(&self) -> String {
-        match serde_json::to_string(&self) {
-            Ok(s) => s,
-            Err(e) => format!("Error: {}", e),
+    // Word timings are relative to the trimmed audio.
+    let offset = audio.offset as f64 / audio::SAMPLE_RATE as f64;
+    let words = recognition
+        .words
+        .into_iter()
+        .map(|w| WordTiming {
+            word: w.word,
+            start: w.start + offset,
+            end: w.end + offset,
+        })
+        .collect();
+
+    Ok(AudioAsText {
+        raw: recognition.text,
+        filetype: audio.description.format.clone(),
+        audio: Some(audio.description),
+        words,
+        confidence: recognition.confidence,
+    })
+}
+
+// A streaming recognition session, fed 16 kHz mono samples as they arrive.
+pub trait SpeechStream {
+    fn feed_audio(&mut self, audio_buffer: &[i16]);
+
+    // Decode the audio fed so far, without ending the session.
+    fn intermediate_decode(&mut self) -> Result<String, String>;
+
+    fn finish(self: Box<Self>) -> Result<String, String>;
+}
+
+// Load the speech-to-text backend selected with --backend.
+pub fn load_backend(config: &Configuration) -> Box<dyn SpeechToText> {
+    match config.backend.as_str() {
+        "fixture" => {
+            println!("Loading speech fixtures from {}...", &config.fixtures);
+            Box::new(FixtureSpeech::new(&config.fixtures))
+        }
+        #[cfg(feature = "deepspeech")]
+        "deepspeech" => {
+            println!("Loading Deepspeech model...");
+            Box::new(KakaiaDeepSpeech::new(config))
+        }
+        backend => {
+            eprintln!(
+                "FATAL ERROR, speech backend '{}' is not available in this build",
+                backend
+            );
+            std::process::exit(1);
         }
     }
 }
 
+#[cfg(feature = "deepspeech")]
 pub struct KakaiaDeepSpeech {
     model: deepspeech::Model,
 }
+#[cfg(feature = "deepspeech")]
 unsafe impl Send for KakaiaDeepSpeech {}
 
+#[cfg(feature = "deepspeech")]
 impl KakaiaDeepSpeech {
-    pub fn new() -> Self {
-        const DEEPSPEECH_MODELS_ENV: &str = "DEEPSPEECH_MODELS";
-        let model_dir = match env::var(DEEPSPEECH_MODELS_ENV) {
-            Ok(d) => d,
+    pub fn new(config: &Configuration) -> Self {
+        let model_path = config.model_path();
+        let mut deepspeech_model = match Model::load_from_files(&model_path, config.beam_width) {
+            Ok(m) => m,
             Err(_) => {
-                let default_dir = env::current_dir().unwrap().join("models/");
-                eprintln!(
-                    "DeepSpeechModel: {} isn't set, defaulting to {:?}",
-                    DEEPSPEECH_MODELS_ENV, default_dir
-                );
-                default_dir.to_str().unwrap().to_string()
+                eprintln!("FATAL ERROR, {:?} is an invalid model", model_path);
+                std::process::exit(1);
             }
         };
-        let dir_path = Path::new(&model_dir);
-        let mut deepspeech_model =
-            match Model::load_from_files(&dir_path.join("output_graph.pb"), BEAM_WIDTH) {
-                Ok(m) => m,
-                Err(_) => {
-                    eprintln!("FATAL ERROR, {:?} is an invalid models path", dir_path);
-                    std::process::exit(1);
-                }
-            };
-        deepspeech_model.enable_decoder_with_lm(
-            &dir_path.join("lm.binary"),
-            &dir_path.join("trie"),
-            LM_WEIGHT,
-            VALID_WORD_COUNT_WEIGHT,
-        );
+        if !config.no_lm {
+            deepspeech_model.enable_decoder_with_lm(
+                &config.lm_path(),
+                &config.trie_path(),
+                config.lm_weight,
+                config.valid_word_count_weight,
+            );
+        }
         KakaiaDeepSpeech {
             model: deepspeech_model,
         }
     }
+}
 
-    pub fn convert_audio_to_text(&mut self, audio_file: std::fs::File) -> AudioAsText {
-        // Read audio from temporary file.
-        let mut reader = match Reader::new(&audio_file) {
-            Ok(r) => r,
-            Err(e) => {
-                let error = format!("failed to load audio file ({:?}): {}\n", audio_file, e);
-                return AudioAsText {
-                    raw: error,
-                    filetype: "unknown".to_string(),
-                };
-            }
+#[cfg(feature = "deepspeech")]
+impl SpeechToText for KakaiaDeepSpeech {
+    fn speech_to_text(&mut self, audio_buffer: &[i16]) -> Result<String, String> {
+        self.model
+            .speech_to_text(audio_buffer)
+            .map_err(|e| e.to_string())
+    }
+
+    fn speech_to_text_with_metadata(&mut self, audio_buffer: &[i16]) -> Result<Recognition, String> {
+        let metadata = match self.model.speech_to_text_with_metadata(audio_buffer) {
+            Ok(m) => m,
+            Err(e) => return Err(format!("{:?}", e)),
         };
 
-        let desc = reader.description();
-        // Validate the audio file.
-        if desc.channel_count() != 1 {
-            let error = format!(
-                "audio file must have exactly 1 track, not {}",
-                desc.channel_count()
-            );
-            eprintln!("{}", &error);
-            return AudioAsText {
-                raw: error,
-                filetype: "unknown".to_string(),
+        // Metadata is a list of characters with their start times, assemble
+        // them into words.
+        let mut text = String::new();
+        let mut words: Vec<WordTiming> = Vec::new();
+        let mut word: Option<WordTiming> = None;
+        for item in metadata.items() {
+            let character = match item.character() {
+                Ok(c) => c,
+                Err(e) => return Err(format!("invalid character in metadata: {}", e)),
             };
+            text.push_str(character);
+            let start = item.start_time() as f64;
+            if character == " " {
+                if let Some(w) = word.take() {
+                    words.push(w);
+                }
+            } else {
+                match &mut word {
+                    Some(w) => {
+                        w.word.push_str(character);
+                        w.end = start + TIMESTEP;
+                    }
+                    None => {
+                        word = Some(WordTiming {
+                            word: character.to_string(),
+                            start,
+                            end: start + TIMESTEP,
+                        });
+                    }
+                }
+            }
+        }
+        if let Some(w) = word.take() {
+            words.push(w);
         }
 
-        // Obtain the buffer of samples
-        let audio_buffer: Vec<_> = if desc.sample_rate() == SAMPLE_RATE {
-            reader.samples().map(|s| s.unwrap()).collect()
-        } else {
-            // We need to interpolate to the target sample rate
-            let interpolator = Linear::new([0i16], [0]);
-            let conv = Converter::from_hz_to_hz(
-                from_iter(reader.samples::<i16>().map(|s| [s.unwrap()])),
-                interpolator,
-                desc.sample_rate() as f64,
-                SAMPLE_RATE as f64,
-            );
-            conv.until_exhausted().map(|v| v[0]).collect()
-        };
+        Ok(Recognition {
+            text,
+            words,
+            confidence: Some(metadata.confidence()),
+        })
+    }
 
-        let extension = match desc.format() {
-            audrey::Format::Flac => "flac".to_string(),
-            audrey::Format::OggVorbis => "ogg".to_string(),
-            audrey::Format::Wav => "wav".to_string(),
-            audrey::Format::CafAlac => "caf".to_string(),
-        };
+    fn create_stream(&mut self) -> Result<Box<dyn SpeechStream + '_>, String> {
+        match self.model.create_stream() {
+            Ok(stream) => Ok(Box::new(KakaiaDeepSpeechStream { stream })),
+            Err(e) => Err(format!("failed to create stream: {:?}", e)),
+        }
+    }
+}
 
-        let text = match self.model.speech_to_text(audio_buffer.as_slice()) {
-            Ok(t) => t,
-            Err(e) => {
-                // @TODO: handle this gracefully
-                eprintln!("Unexpected error converting audio to text: {}", e);
-                "Unexpected error: failed to convert audio to text".to_string()
-            }
-        };
+#[cfg(feature = "deepspeech")]
+pub struct KakaiaDeepSpeechStream<'a> {
+    stream: deepspeech::Stream<'a>,
+}
 
-        AudioAsText {
-            raw: text,
-            filetype: extension,
-        }
+#[cfg(feature = "deepspeech")]
+impl<'a> SpeechStream for KakaiaDeepSpeechStream<'a> {
+    fn feed_audio(&mut self, audio_buffer: &[i16]) {
+        self.stream.feed_audio(audio_buffer);
+    }
+
+    fn intermediate_decode(&mut self) -> Result<String, String> {
+        self.stream
+            .intermediate_decode()
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, String> {
+        self.stream.finish().map_err(|e| format!("{:?}", e))
     }
 }
 
 pub async fn _audio_to_text(
     config: web::Data<Configuration>,
-    deepspeech_data: web::Data<Mutex<KakaiaDeepSpeech>>,
-    nlu_data: web::Data<Mutex<NLU>>,
-    base64_audio: String,
+    registry: web::Data<Registry>,
+    speech_pool: web::Data<Pool<Box<dyn SpeechToText>>>,
+    nlu_pool: web::Data<Pool<NLU>>,
+    req: HttpRequest,
+    payload: web::Payload,
 ) -> HttpResponse {
-    let mut kakaia_deepspeech = deepspeech_data.lock().unwrap();
-    let nlu = nlu_data.lock().unwrap();
-
-    // Load audio.bytes from String
-    let audio_bytes = match base64::decode(&base64_audio) {
+    let raw = match upload::raw_pcm(&req) {
+        Ok(r) => r,
+        Err(error) => return error.http_response(""),
+    };
+    let audio_bytes = match upload::read_audio(&req, payload, config.bytes).await {
         Ok(audio) => audio,
-        Err(e) => {
-            // @TODO: logging, properly handle this error
-            let error = format!("failed to decode audio.data: {}\n", e);
-            eprint!("{}", &error);
-            let kakaia_response = KakaiaResponse::new("none", "unexpected error decoding audio data", &error, 0.0);
-            return HttpResponse::InternalServerError()
-                .content_type("application/json")
-                .body(kakaia_response.to_json_string());
-        }
+        Err(error) => return error.http_response(""),
     };
 
-    // Create a temporary file.
-    let mut temporary_file = match NamedTempFile::new() {
-        Ok(f) => f,
-        Err(e) => {
-            let error = format!("failed to create temporary file: {}", e);
-            let kakaia_response = KakaiaResponse::new("none", "unexpected error creating a temporary file", &error, 0.0);
-            return HttpResponse::InternalServerError()
-                .content_type("application/json")
-                .body(kakaia_response.to_json_string());
-        }
-    };
+    // Speech recognition blocks, so run it on the thread pool.
+    let config = config.get_ref().clone();
+    let speech_pool = speech_pool.get_ref().clone();
+    let nlu_pool = nlu_pool.get_ref().clone();
+    let processed =
+        web::block(move || process_audio(&config, &registry, &speech_pool, &nlu_pool, audio_bytes, raw)).await;
 
-    // Grab a pointer to the beginning of the file.
-    let audio_file = match temporary_file.reopen() {
-        Ok(a) => a,
-        Err(e) => {
-            let error = format!("failed to open temporary file: {}", e);
-            let kakaia_response = KakaiaResponse::new("none", "unexpected error opening a temporary file", &error, 0.0);
-            return HttpResponse::InternalServerError()
+    match processed {
+        Ok(kakaia_response) => {
+            // Debug output for now
+            println!("{:?}", &kakaia_response);
+            HttpResponse::Ok()
                 .content_type("application/json")
-                .body(kakaia_response.to_json_string());
+                .body(kakaia_response.to_json_string())
         }
-    };
-
-    // Write audio.bytes into temporary file.
-    let mut pos = 0;
-    while pos < audio_bytes.len() {
-        let bytes_written = match temporary_file.write(&audio_bytes[pos..]) {
-            Ok(b) => b,
-            Err(e) => {
-                let error = format!("failed to create temporary file: {}", e);
-                let kakaia_response = KakaiaResponse::new("none", "unexpected error creating a temporary file", &error, 0.0);
-                return HttpResponse::InternalServerError()
-                    .content_type("application/json")
-                    .body(kakaia_response.to_json_string());
-            }
-        };
-        pos += bytes_written;
+        Err(BlockingError::Error((error, text))) => error.http_response(&text),
+        Err(BlockingError::Canceled) => KakaiaError::Canceled.http_response(""),
     }
+}
 
-    // Convert audio file to text.
-    let converted: AudioAsText = kakaia_deepspeech.convert_audio_to_text(audio_file);
+// Convert audio to text and run the resulting command, returning the error
+// and any text recognized before it on failure.
+fn process_audio(
+    config: &Configuration,
+    registry: &Registry,
+    speech_pool: &Pool<Box<dyn SpeechToText>>,
+    nlu_pool: &Pool<NLU>,
+    audio_bytes: Vec<u8>,
+    raw: Option<RawPcm>,
+) -> Result<KakaiaResponse, (KakaiaError, String)> {
+    // Decoding, resampling and trimming silence don't need a recognizer, which
+    // is only checked out to convert the audio to text.
+    let converted = decode_audio(Cursor::new(audio_bytes.as_slice()), raw, config)
+        .and_then(|audio| convert_audio_to_text(speech_pool, audio));
 
-    // Optionally store a copy of the audio and text
+    // Optionally store a copy of the audio and text, even if it couldn't be
+    // converted.
     if config.store {
-        let now: DateTime<Utc> = Utc::now();
-        let archive_directory = format!(
-            "archive/{}/{}/{}/",
-            now.format("%Y"),
-            now.format("%m"),
-            now.format("%d")
-        );
-        match std::fs::create_dir_all(&archive_directory) {
-            Ok(_) => {
-                let hour = now.format("%H");
-                let minute = now.format("%M");
-                let second = now.format("%S");
-                let mut buffer = match std::fs::File::create(format!(
-                    "{}/audio-{}-{}-{}.{}",
-                    archive_directory, &hour, &minute, &second, converted.filetype
-                )) {
-                    Ok(b) => b,
-                    Err(e) => {
-                        // @TODO: deal with this gracefully
-                        let error = format!("failed to create archive copy of audio file: {}", e);
-                        let kakaia_response = KakaiaResponse::new("none", "unexpected error archiving a copy of audio file", &error, 0.0);
-                        return HttpResponse::InternalServerError()
-                            .content_type("application/json")
-                            .body(kakaia_response.to_json_string());
-                    }
-                };
-                // Write audio.bytes into temporary file.
-                let mut pos = 0;
-                while pos < audio_bytes.len() {
-                    let bytes_written = match buffer.write(&audio_bytes[pos..]) {
-                        Ok(b) => b,
-                        Err(e) => {
-                            let error = format!("failed to write archive file: {}", e);
-                            let kakaia_response = KakaiaResponse::new("none", "unexpected error writing a copy of audio file", &error, 0.0);
-                            return HttpResponse::InternalServerError()
-                                .content_type("application/json")
-                                .body(kakaia_response.to_json_string());
-                        }
-                    };
-                    pos += bytes_written;
-                }
-                let mut buffer = match std::fs::File::create(format!(
-                    "{}/audio-{}-{}-{}.txt",
-                    archive_directory, &hour, &minute, &second
-                )) {
-                    Ok(b) => b,
-                    Err(e) => {
-                        // @TODO: deal with this gracefully
-                        let error = format!("failed to create archive text conversion of audio file: {}", e);
-                        let kakaia_response = KakaiaResponse::new("none", "unexpected error writing text conversion of audio file", &error, 0.0);
-                        return HttpResponse::InternalServerError()
-                            .content_type("application/json")
-                            .body(kakaia_response.to_json_string());
-                    }
-                };
-                match writeln!(buffer, "{}", &converted.raw) {
-                    Ok(_) => (),
-                    Err(e) => eprintln!("failed to archive text conversion of audio file: {}", e),
-                }
-            }
-            Err(e) => {
-                let error = format!("failed to create directory '{}': {}", &archive_directory, e);
-                let kakaia_response = KakaiaResponse::new("none", "unexpected error creating directory", &error, 0.0);
-                return HttpResponse::InternalServerError()
-                    .content_type("application/json")
-                    .body(kakaia_response.to_json_string());
-            }
+        let (filetype, text) = match &converted {
+            Ok(c) => (c.filetype.as_str(), c.raw.clone()),
+            Err(e) => ("unknown", e.to_string()),
+        };
+        if let Err(e) = archive(&audio_bytes, filetype, &text) {
+            return Err((e, text));
         }
     }
+    let converted = match converted {
+        Ok(c) => c,
+        Err(e) => return Err((e, "".to_string())),
+    };
 
-    let parsed_json = nlu.parse(&converted.raw);
-    //println!("NLU: {:?}", &parsed_json);
-
-    let command_string = nlu.get_command(&parsed_json);
-    let kakaia_command = KakaiaCommand::from_str(&command_string);
-
-    let kakaia_response: KakaiaResponse = match kakaia_command.command {
-        // no command, we do nothing
-        KakaiaCommandType::None => {
-            KakaiaResponse::new(
-                "none",
-                "no command",
-                &converted.raw,
-                0.0
-            )
+    let speech_detected = match &converted.audio {
+        Some(audio) => audio.speech_duration != Some(0.0),
+        None => true,
+    };
+    let mut kakaia_response = if speech_detected {
+        let nlu = nlu_pool.checkout().map_err(|e| (e, converted.raw.clone()))?;
+        match command::run_command(registry, &nlu, &converted.raw) {
+            Ok(r) => r,
+            Err(e) => return Err((e, converted.raw)),
         }
-        // setTimer command, return how many seconds the timer should run
-        KakaiaCommandType::SetTimer => {
-            if nlu.has_expected_slots(&parsed_json, 1) {
-                let seconds_value = nlu.get_slot_value(&parsed_json, "snips/duration", "duration");
-                let seconds = nlu.duration_as_seconds(seconds_value.unwrap());
-                KakaiaResponse::new(
-                    &kakaia_command.string,
-                    format!("set timer for {} seconds", seconds).as_str(),
-                    &converted.raw,
-                    seconds
-                )
-            } else {
-                KakaiaResponse::new(
-                    "none",
-                    "not understood",
-                    &converted.raw,
-                    0.0
-                )
+    } else {
+        KakaiaResponse::new("none", "no speech detected", &converted.raw, 0.0)
+    };
+    kakaia_response.audio = converted.audio;
+    kakaia_response.words = converted.words;
+    kakaia_response.confidence = converted.confidence;
+    Ok(kakaia_response)
+}
 
-            }
-        }
-        // convertTemperature command, return converted temperature
-        KakaiaCommandType::ConvertTemperature => {
-            println!("ConvertTemperature: {:?}", parsed_json);
-            if nlu.has_expected_slots(&parsed_json, 2) {
-                let from_value = nlu.get_slot_value(&parsed_json, "snips/temperature", "from");
-                let from_degrees = nlu.get_float(from_value);
-                let from_scale = nlu.get_string_custom(from_value, "unit");
-                let to_value = nlu.get_slot_value(&parsed_json, "temperature_name", "to");
-                let to_scale = nlu.get_string(to_value);
-                let result = match from_scale.as_str() {
-                    "celsius" => {
-                        match to_scale.as_str() {
-                            "fahrenheit" => {
-                                eprintln!("{} * 1.8 + 32", from_degrees);
-                                from_degrees * 1.8 + 32.0
-                            },
-                            "kelvin" => {
-                                from_degrees + 273.15
-                            }
-                            _ => {
-                                eprintln!("{} to {} fell through", from_scale, to_scale);
-                                0.0
-                            }
-                        }
-                    },
-                    "fahrenheit" => {
-                        match to_scale.as_str() {
-                            "celsius" => {
-                                (from_degrees - 32.0) / 1.8
-                            },
-                            "kelvin" => {
-                                (from_degrees - 32.0) / 1.8 + 273.15
-                            }
-                            _ => {
-                                eprintln!("{} to {} fell through", from_scale, to_scale);
-                                0.0
-                            }
-                        }
-                    },
-                    "kelvin" => {
-                        match to_scale.as_str() {
-                            "celsius" => {
-                                from_degrees - 273.15
-                            },
-                            "fahrenheit" => {
-                                (from_degrees - 273.15) * 1.8 + 32.0
-                            }
-                            _ => {
-                                eprintln!("{} to {} fell through", from_scale, to_scale);
-                                0.0
-                            }
-                        }
-                    },
-                    _ => {
-                        eprintln!("{} to {} fell through", from_scale, to_scale);
-                        0.0
-                    }
-                };
-                KakaiaResponse::new(
-                    &kakaia_command.string,
-                    format!("{} degrees {} is {} degrees {}", from_degrees, from_scale, result, to_scale).as_str(),
-                    &converted.raw,
-                    result
-                )
-            } else {
-                KakaiaResponse::new(
-                    "none",
-                    "not understood",
-                    &converted.raw,
-                    0.0
-                )
-            }
-        }
-        // simpleCalculation command, return result of calculation
-        KakaiaCommandType::SimpleCalculation => {
-            //println!("SimpleCalculation: {:?}", parsed_json);
-            if nlu.has_expected_slots(&parsed_json, 3) {
-                let first_value = nlu.get_slot_value(&parsed_json, "snips/number", "first");
-                let first = nlu.get_float(first_value);
-                let second_value = nlu.get_slot_value(&parsed_json, "snips/number", "second");
-                let second = nlu.get_float(second_value);
-                let operation_value = nlu.get_slot_value(&parsed_json, "operation", "operation");
-                let operation = nlu.get_string(operation_value);
-                let operation_string;
-                let result = match operation.as_str() {
-                    "plus" => { 
-                        operation_string = operation.to_string();
-                        first + second
-                    }
-                    "minus" => {
-                        operation_string = operation.to_string();
-                        first - second
-                    }
-                    "multiply" => {
-                        operation_string = "times".to_string();
-                        first * second
-                    }
-                    "divide" => {
-                        operation_string = "divided by".to_string();
-                        first / second
-                    }
-                    _ => {
-                        operation_string = "???".to_string();
-                        0.0
-                    }
-                };
-                KakaiaResponse::new(
-                    &kakaia_command.string,
-                    format!("{} {} {} equals {}", first, operation_string, second, result).as_str(),
-                    &converted.raw,
-                    result
-                )
-            } else {
-                KakaiaResponse::new(
-                    "none",
-                    "not understood",
-                    &converted.raw,
-                    0.0
-                )
-            }
+// Store a copy of the audio file and its text under archive/YYYY/MM/DD/.
+fn archive(audio_bytes: &[u8], filetype: &str, text: &str) -> Result<(), KakaiaError> {
+    let now: DateTime<Utc> = Utc::now();
+    let archive_directory = format!(
+        "archive/{}/{}/{}/",
+        now.format("%Y"),
+        now.format("%m"),
+        now.format("%d")
+    );
+    if let Err(e) = std::fs::create_dir_all(&archive_directory) {
+        return Err(KakaiaError::Archive(format!(
+            "failed to create directory '{}': {}",
+            &archive_directory, e
+        )));
+    }
+    let hour = now.format("%H");
+    let minute = now.format("%M");
+    let second = now.format("%S");
+
+    let mut buffer = match std::fs::File::create(format!(
+        "{}/audio-{}-{}-{}.{}",
+        archive_directory, &hour, &minute, &second, filetype
+    )) {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(KakaiaError::Archive(format!(
+                "failed to create archive copy of audio file: {}",
+                e
+            )))
         }
     };
+    if let Err(e) = buffer.write_all(audio_bytes) {
+        return Err(KakaiaError::Archive(format!("failed to write archive file: {}", e)));
+    }
 
-    // Debug output for now
-    println!("{:?}", &kakaia_response);
-    return HttpResponse::Ok()
-        .content_type("application/json")
-        .body(kakaia_response.to_json_string());
+    let mut buffer = match std::fs::File::create(format!(
+        "{}/audio-{}-{}-{}.txt",
+        archive_directory, &hour, &minute, &second
+    )) {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(KakaiaError::Archive(format!(
+                "failed to create archive text conversion of audio file: {}",
+                e
+            )))
+        }
+    };
+    match writeln!(buffer, "{}", text) {
+        Ok(_) => (),
+        Err(e) => eprintln!("failed to archive text conversion of audio file: {}", e),
+    }
+    Ok(())
 }
@@ -0,0 +1,305 @@
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, Message, StreamHandler};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use serde_json::json;
+
+use crate::command::{self, KakaiaResponse, Registry};
+use crate::error::KakaiaError;
+use crate::nlu::NLU;
+use crate::pool::Pool;
+use crate::speech::SpeechToText;
+use crate::Configuration;
+
+// How often the client is pinged, how long it may go without sending audio or
+// text before the session is dropped, and how long a session may last.
+const HEARTBEAT: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_SESSION: Duration = Duration::from_secs(300);
+// Chunks of samples waiting for the recognition thread, beyond which the
+// client is sending audio faster than it can be recognized.
+const MAX_PENDING_CHUNKS: usize = 256;
+
+// A WebSocket speech recognition session. The client sends binary messages of
+// 16-bit little-endian 16 kHz mono PCM, and the text message "end" once done.
+// Intermediate transcripts are pushed back as {"partial": "..."} whenever they
+// change, followed by the final KakaiaResponse, after which the socket closes.
+pub struct SpeechSession {
+    registry: web::Data<Registry>,
+    speech_pool: Pool<Box<dyn SpeechToText>>,
+    nlu_pool: Pool<NLU>,
+    // Chunks of samples for the recognition thread, dropped at end of stream.
+    sender: Option<mpsc::SyncSender<Vec<i16>>>,
+    // Odd trailing byte of the previous binary message.
+    remainder: Option<u8>,
+    // Max bytes of audio for the whole stream, and bytes received so far.
+    limit: usize,
+    received: usize,
+    // When the session started, and when the client last sent audio or text.
+    // Pongs don't count, so a client can't hold a session open without
+    // streaming.
+    started: Instant,
+    heartbeat: Instant,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Partial(pub String);
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Final(pub KakaiaResponse);
+
+impl SpeechSession {
+    pub fn new(
+        registry: web::Data<Registry>,
+        speech_pool: Pool<Box<dyn SpeechToText>>,
+        nlu_pool: Pool<NLU>,
+        limit: usize,
+    ) -> Self {
+        SpeechSession {
+            registry,
+            speech_pool,
+            nlu_pool,
+            sender: None,
+            remainder: None,
+            limit,
+            received: 0,
+            started: Instant::now(),
+            heartbeat: Instant::now(),
+        }
+    }
+
+    // Answer with an error and close the session. Dropping the sender lets the
+    // recognition thread finish, and it won't answer a closed session.
+    fn fail(&mut self, error: KakaiaError, ctx: &mut ws::WebsocketContext<Self>) {
+        eprintln!("{}: {}", error.code(), &error);
+        self.sender = None;
+        ctx.text(error.response("").to_json_string());
+        ctx.close(None);
+        ctx.stop();
+    }
+
+    // Convert little-endian bytes to samples, carrying an odd byte over to the
+    // next message.
+    fn samples(&mut self, bytes: &[u8]) -> Vec<i16> {
+        let mut samples = Vec::with_capacity(bytes.len() / 2 + 1);
+        let mut bytes = bytes.iter();
+        if let Some(low) = self.remainder.take() {
+            match bytes.next() {
+                Some(high) => samples.push(i16::from_le_bytes([low, *high])),
+                None => self.remainder = Some(low),
+            }
+        }
+        let bytes = bytes.as_slice();
+        for pair in bytes.chunks(2) {
+            if pair.len() == 2 {
+                samples.push(i16::from_le_bytes([pair[0], pair[1]]));
+            } else {
+                self.remainder = Some(pair[0]);
+            }
+        }
+        samples
+    }
+}
+
+impl Actor for SpeechSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT, |session, ctx| {
+            if session.started.elapsed() > MAX_SESSION {
+                let error = KakaiaError::Upload(format!(
+                    "the stream lasted longer than {} seconds",
+                    MAX_SESSION.as_secs()
+                ));
+                session.fail(error, ctx);
+                return;
+            }
+            if Instant::now().duration_since(session.heartbeat) > CLIENT_TIMEOUT {
+                eprintln!("WebSocket client timed out");
+                session.sender = None;
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+        let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_CHUNKS);
+        self.sender = Some(sender);
+        let speech_pool = self.speech_pool.clone();
+        let nlu_pool = self.nlu_pool.clone();
+        let registry = self.registry.clone();
+        let address = ctx.address();
+        thread::spawn(move || recognize(registry, speech_pool, nlu_pool, receiver, address));
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Binary(bytes)) => {
+                self.heartbeat = Instant::now();
+                if self.sender.is_none() {
+                    return;
+                }
+                self.received += bytes.len();
+                if self.received > self.limit {
+                    self.fail(KakaiaError::TooLarge(self.limit), ctx);
+                    return;
+                }
+                let samples = self.samples(&bytes);
+                if let Some(sender) = &self.sender {
+                    match sender.try_send(samples) {
+                        Err(TrySendError::Full(_)) => self.fail(KakaiaError::TooFast, ctx),
+                        // The recognition thread only goes away on error, in
+                        // which case it has already sent a final response.
+                        Err(TrySendError::Disconnected(_)) | Ok(()) => (),
+                    }
+                }
+            }
+            Ok(ws::Message::Text(text)) => {
+                self.heartbeat = Instant::now();
+                if text.trim() == "end" {
+                    // Dropping the sender ends the stream.
+                    self.sender = None;
+                }
+            }
+            Ok(ws::Message::Close(_)) => {
+                self.sender = None;
+                ctx.stop();
+            }
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("WebSocket protocol error: {}", e);
+                self.sender = None;
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl Handler<Partial> for SpeechSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: Partial, ctx: &mut Self::Context) {
+        ctx.text(json!({ "partial": msg.0 }).to_string());
+    }
+}
+
+impl Handler<Final> for SpeechSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: Final, ctx: &mut Self::Context) {
+        ctx.text(msg.0.to_json_string());
+        ctx.close(None);
+        ctx.stop();
+    }
+}
+
+// Feed streamed samples to the speech-to-text backend until the sender is
+// dropped, then run the transcript through the NLU engine. A recognizer is
+// checked out of the pool once the first audio arrives, until the end of the
+// stream.
+fn recognize(
+    registry: web::Data<Registry>,
+    speech_pool: Pool<Box<dyn SpeechToText>>,
+    nlu_pool: Pool<NLU>,
+    receiver: mpsc::Receiver<Vec<i16>>,
+    address: Addr<SpeechSession>,
+) {
+    let first = match receiver.recv() {
+        Ok(samples) => samples,
+        Err(_) => {
+            address.do_send(Final(KakaiaResponse::new("none", "no speech detected", "", 0.0)));
+            return;
+        }
+    };
+    let mut speech = match speech_pool.checkout() {
+        Ok(s) => s,
+        Err(error) => {
+            eprintln!("{}: {}", error.code(), &error);
+            address.do_send(Final(error.response("")));
+            return;
+        }
+    };
+    let mut stream = match speech.create_stream() {
+        Ok(s) => s,
+        Err(e) => {
+            let error = KakaiaError::Recognition(e);
+            eprintln!("{}: {}", error.code(), &error);
+            address.do_send(Final(error.response("")));
+            return;
+        }
+    };
+
+    let mut partial = "".to_string();
+    let mut next = Some(first);
+    while let Some(samples) = next {
+        stream.feed_audio(&samples);
+        // Catch up with any chunks that arrived meanwhile before decoding.
+        for samples in receiver.try_iter() {
+            stream.feed_audio(&samples);
+        }
+        match stream.intermediate_decode() {
+            Ok(text) => {
+                if text != partial {
+                    partial = text;
+                    address.do_send(Partial(partial.clone()));
+                }
+            }
+            Err(e) => eprintln!("Unexpected error decoding partial audio: {}", e),
+        }
+        next = receiver.recv().ok();
+    }
+
+    let text = match stream.finish() {
+        Ok(t) => t,
+        Err(e) => {
+            let error = KakaiaError::Recognition(format!("failed to convert audio to text: {}", e));
+            eprintln!("{}: {}", error.code(), &error);
+            address.do_send(Final(error.response(&partial)));
+            return;
+        }
+    };
+    drop(speech);
+    // The session was closed by an error or a timeout.
+    if !address.connected() {
+        return;
+    }
+
+    let kakaia_response = match nlu_pool
+        .checkout()
+        .and_then(|nlu| command::run_command(&registry, &nlu, &text))
+    {
+        Ok(r) => r,
+        Err(error) => {
+            eprintln!("{}: {}", error.code(), &error);
+            error.response(&text)
+        }
+    };
+    address.do_send(Final(kakaia_response));
+}
+
+pub async fn _audio_stream(
+    config: web::Data<Configuration>,
+    req: HttpRequest,
+    stream: web::Payload,
+    registry: web::Data<Registry>,
+    speech_pool: web::Data<Pool<Box<dyn SpeechToText>>>,
+    nlu_pool: web::Data<Pool<NLU>>,
+) -> Result<HttpResponse, Error> {
+    ws::start(
+        SpeechSession::new(
+            registry,
+            speech_pool.get_ref().clone(),
+            nlu_pool.get_ref().clone(),
+            config.bytes,
+        ),
+        &req,
+        stream,
+    )
+}
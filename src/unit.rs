@@ -0,0 +1,274 @@
+// Units of measurement for convertUnit, each a multiple of the base unit of
+// its dimension. Unit names are the values of the unit entity of the NLU
+// dataset (nlu/dataset.yaml), so the two must be kept in sync.
+use std::fmt;
+
+use self::Dimension::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Length,
+    Mass,
+    Volume,
+    Area,
+    Speed,
+    DataSize,
+    Time,
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Dimension::Length => "length",
+            Dimension::Mass => "mass",
+            Dimension::Volume => "volume",
+            Dimension::Area => "area",
+            Dimension::Speed => "speed",
+            Dimension::DataSize => "data size",
+            Dimension::Time => "time",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub name: &'static str,
+    pub plural: &'static str,
+    pub dimension: Dimension,
+    // The unit in base units: meters, kilograms, cubic meters, square meters,
+    // meters per second, bytes or seconds.
+    pub factor: f64,
+}
+
+// Why a value couldn't be converted.
+#[derive(Debug)]
+pub enum ConversionError {
+    UnknownUnit(String),
+    Incompatible { from: Unit, to: Unit },
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::UnknownUnit(name) => write!(f, "unknown unit '{}'", name),
+            ConversionError::Incompatible { from, to } => write!(
+                f,
+                "cannot convert {} ({}) to {} ({})",
+                from.plural, from.dimension, to.plural, to.dimension
+            ),
+        }
+    }
+}
+
+// Exact definitions are used where there are some, such as the international
+// inch and pound, and US customary volumes.
+const UNITS: &[(&str, &str, Dimension, f64)] = &[
+    ("millimeter", "millimeters", Length, 0.001),
+    ("centimeter", "centimeters", Length, 0.01),
+    ("meter", "meters", Length, 1.0),
+    ("kilometer", "kilometers", Length, 1000.0),
+    ("inch", "inches", Length, 0.0254),
+    ("foot", "feet", Length, 0.3048),
+    ("yard", "yards", Length, 0.9144),
+    ("mile", "miles", Length, 1609.344),
+    ("nautical mile", "nautical miles", Length, 1852.0),
+    ("milligram", "milligrams", Mass, 0.000_001),
+    ("gram", "grams", Mass, 0.001),
+    ("kilogram", "kilograms", Mass, 1.0),
+    ("tonne", "tonnes", Mass, 1000.0),
+    ("ounce", "ounces", Mass, 0.028_349_523_125),
+    ("pound", "pounds", Mass, 0.453_592_37),
+    ("stone", "stone", Mass, 6.350_293_18),
+    ("ton", "tons", Mass, 907.184_74),
+    ("milliliter", "milliliters", Volume, 0.000_001),
+    ("centiliter", "centiliters", Volume, 0.000_01),
+    ("liter", "liters", Volume, 0.001),
+    ("cubic meter", "cubic meters", Volume, 1.0),
+    ("teaspoon", "teaspoons", Volume, 0.000_004_928_921_593_75),
+    ("tablespoon", "tablespoons", Volume, 0.000_014_786_764_781_25),
+    ("fluid ounce", "fluid ounces", Volume, 0.000_029_573_529_562_5),
+    ("cup", "cups", Volume, 0.000_236_588_236_5),
+    ("pint", "pints", Volume, 0.000_473_176_473),
+    ("quart", "quarts", Volume, 0.000_946_352_946),
+    ("gallon", "gallons", Volume, 0.003_785_411_784),
+    ("square centimeter", "square centimeters", Area, 0.0001),
+    ("square meter", "square meters", Area, 1.0),
+    ("hectare", "hectares", Area, 10_000.0),
+    ("square kilometer", "square kilometers", Area, 1_000_000.0),
+    ("square inch", "square inches", Area, 0.000_645_16),
+    ("square foot", "square feet", Area, 0.092_903_04),
+    ("square yard", "square yards", Area, 0.836_127_36),
+    ("acre", "acres", Area, 4_046.856_422_4),
+    ("square mile", "square miles", Area, 2_589_988.110_336),
+    ("meter per second", "meters per second", Speed, 1.0),
+    ("kilometer per hour", "kilometers per hour", Speed, 1.0 / 3.6),
+    ("foot per second", "feet per second", Speed, 0.3048),
+    ("mile per hour", "miles per hour", Speed, 0.447_04),
+    ("knot", "knots", Speed, 1852.0 / 3600.0),
+    ("bit", "bits", DataSize, 0.125),
+    ("byte", "bytes", DataSize, 1.0),
+    ("kilobyte", "kilobytes", DataSize, 1e3),
+    ("megabyte", "megabytes", DataSize, 1e6),
+    ("gigabyte", "gigabytes", DataSize, 1e9),
+    ("terabyte", "terabytes", DataSize, 1e12),
+    ("kibibyte", "kibibytes", DataSize, 1024.0),
+    ("mebibyte", "mebibytes", DataSize, 1_048_576.0),
+    ("gibibyte", "gibibytes", DataSize, 1_073_741_824.0),
+    ("millisecond", "milliseconds", Time, 0.001),
+    ("second", "seconds", Time, 1.0),
+    ("minute", "minutes", Time, 60.0),
+    ("hour", "hours", Time, 3600.0),
+    ("day", "days", Time, 86_400.0),
+    ("week", "weeks", Time, 604_800.0),
+    // A Julian year, of 365.25 days.
+    ("year", "years", Time, 31_557_600.0),
+];
+
+impl Unit {
+    // The unit of the given name, singular or plural, ignoring case.
+    pub fn find(name: &str) -> Result<Unit, ConversionError> {
+        let name = name.trim();
+        match UNITS
+            .iter()
+            .find(|(n, p, _, _)| n.eq_ignore_ascii_case(name) || p.eq_ignore_ascii_case(name))
+        {
+            Some(&(name, plural, dimension, factor)) => Ok(Unit {
+                name,
+                plural,
+                dimension,
+                factor,
+            }),
+            None => Err(ConversionError::UnknownUnit(name.to_string())),
+        }
+    }
+
+    // The name to use after the value, "1 foot" but "2 feet".
+    pub fn name_for(&self, value: f64) -> &'static str {
+        if value == 1.0 {
+            self.name
+        } else {
+            self.plural
+        }
+    }
+}
+
+// Convert a value between units of the same dimension.
+pub fn convert(value: f64, from: &Unit, to: &Unit) -> Result<f64, ConversionError> {
+    if from.dimension != to.dimension {
+        return Err(ConversionError::Incompatible { from: *from, to: *to });
+    }
+    Ok(value * from.factor / to.factor)
+}
+
+// Round to a number of significant digits, to hide floating point noise such
+// as 0.30000000000000004 in human-readable results.
+pub fn round_significant(value: f64, digits: i32) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    let scale = 10f64.powi(digits - 1 - value.abs().log10().floor() as i32);
+    // Values as tiny as subnormals would need a scale beyond f64.
+    if !scale.is_finite() {
+        return value;
+    }
+    (value * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(name: &str) -> Unit {
+        Unit::find(name).unwrap()
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() <= expected.abs() * 1e-12, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn table_has_unique_names_and_positive_factors() {
+        let mut names = std::collections::HashSet::new();
+        for (name, plural, _, factor) in UNITS {
+            assert!(factor.is_finite() && *factor > 0.0, "{} has factor {}", name, factor);
+            assert!(names.insert(name.to_lowercase()), "{} is listed twice", name);
+            // Some units, such as stone, have the same plural.
+            if plural != name {
+                assert!(names.insert(plural.to_lowercase()), "{} is listed twice", plural);
+            }
+        }
+    }
+
+    #[test]
+    fn dataset_units_are_in_the_table() {
+        let dataset: serde_json::Value = serde_json::from_str(include_str!("../nlu/dataset.json")).unwrap();
+        let values = dataset["entities"]["unit"]["data"].as_array().unwrap();
+        assert!(!values.is_empty());
+        for value in values {
+            let name = value["value"].as_str().unwrap();
+            assert!(Unit::find(name).is_ok(), "unit '{}' of the dataset isn't in the table", name);
+        }
+    }
+
+    #[test]
+    fn find_by_singular_or_plural_ignoring_case() {
+        assert_eq!(unit("foot"), unit("Feet"));
+        assert_eq!(unit(" kilometers ").name, "kilometer");
+        assert_eq!(unit("stone").plural, "stone");
+        match Unit::find("furlong") {
+            Err(ConversionError::UnknownUnit(name)) => assert_eq!(name, "furlong"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(unit("foot").name_for(1.0), "foot");
+        assert_eq!(unit("foot").name_for(2.0), "feet");
+        assert_eq!(unit("foot").name_for(0.5), "feet");
+    }
+
+    #[test]
+    fn convert_within_a_dimension() {
+        assert_close(convert(1.0, &unit("mile"), &unit("kilometer")).unwrap(), 1.609_344);
+        assert_close(convert(1.0, &unit("inch"), &unit("centimeter")).unwrap(), 2.54);
+        assert_close(convert(1.0, &unit("gallon"), &unit("liter")).unwrap(), 3.785_411_784);
+        assert_close(convert(16.0, &unit("ounce"), &unit("pound")).unwrap(), 1.0);
+        assert_close(convert(36.0, &unit("kilometer per hour"), &unit("meter per second")).unwrap(), 10.0);
+        assert_close(convert(1.0, &unit("gibibyte"), &unit("mebibyte")).unwrap(), 1024.0);
+        assert_close(convert(8.0, &unit("bit"), &unit("byte")).unwrap(), 1.0);
+        assert_close(convert(2.0, &unit("week"), &unit("day")).unwrap(), 14.0);
+        assert_eq!(convert(3.0, &unit("meter"), &unit("meter")).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn convert_rejects_other_dimensions() {
+        let error = convert(1.0, &unit("meter"), &unit("kilogram")).unwrap_err();
+        match &error {
+            ConversionError::Incompatible { from, to } => {
+                assert_eq!(from.dimension, Length);
+                assert_eq!(to.dimension, Mass);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(error.to_string(), "cannot convert meters (length) to kilograms (mass)");
+        let error = convert(1.0, &unit("gigabyte"), &unit("hour")).unwrap_err();
+        assert_eq!(error.to_string(), "cannot convert gigabytes (data size) to hours (time)");
+    }
+
+    #[test]
+    fn round_significant_digits() {
+        assert_eq!(round_significant(0.1 + 0.2, 6), 0.3);
+        assert_eq!(round_significant(1_234_567.0, 3), 1_230_000.0);
+        assert_eq!(round_significant(0.000_123_456, 3), 0.000_123);
+        assert_eq!(round_significant(-2.543_21, 2), -2.5);
+        assert_eq!(round_significant(999_999.7, 6), 1_000_000.0);
+        assert_eq!(round_significant(0.0, 6), 0.0);
+        assert_eq!(round_significant(f64::INFINITY, 6), f64::INFINITY);
+        assert!(round_significant(f64::NAN, 6).is_nan());
+    }
+
+    #[test]
+    fn round_significant_leaves_tiny_values() {
+        let subnormal = 5e-324;
+        assert_eq!(round_significant(subnormal, 6), subnormal);
+        assert_eq!(round_significant(-1e-310, 6), -1e-310);
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use actix_multipart::Multipart;
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest};
+use futures::{Stream, StreamExt};
+
+use crate::codec::RawPcm;
+use crate::error::KakaiaError;
+
+// Headerless PCM uploads are declared with format=pcm, and optionally rate and
+// channels, in the query string, or with the equivalent X-Audio-Format,
+// X-Audio-Sample-Rate and X-Audio-Channels headers. Defaults to 16 kHz mono.
+pub fn raw_pcm(req: &HttpRequest) -> Result<Option<RawPcm>, KakaiaError> {
+    let query = match web::Query::<HashMap<String, String>>::from_query(req.query_string()) {
+        Ok(q) => q.into_inner(),
+        Err(e) => return Err(KakaiaError::Upload(format!("invalid query string: {}", e))),
+    };
+    let parameter = |name: &str, header_name: &str| -> Option<String> {
+        match query.get(name) {
+            Some(v) => Some(v.to_string()),
+            None => match req.headers().get(header_name) {
+                Some(v) => v.to_str().ok().map(|v| v.trim().to_string()),
+                None => None,
+            },
+        }
+    };
+
+    match parameter("format", "x-audio-format") {
+        Some(format) => {
+            if format.to_lowercase() != "pcm" {
+                return Err(KakaiaError::Upload(format!(
+                    "unsupported audio format '{}', only 'pcm' can be declared",
+                    format
+                )));
+            }
+        }
+        None => return Ok(None),
+    }
+    let sample_rate = match parameter("rate", "x-audio-sample-rate") {
+        Some(r) => match r.parse() {
+            Ok(r) if r > 0 => r,
+            _ => return Err(KakaiaError::Upload(format!("invalid sample rate '{}'", r))),
+        },
+        None => 16_000,
+    };
+    let channels = match parameter("channels", "x-audio-channels") {
+        Some(c) => match c.parse() {
+            Ok(c) if c > 0 => c,
+            _ => return Err(KakaiaError::Upload(format!("invalid channel count '{}'", c))),
+        },
+        None => 1,
+    };
+    Ok(Some(RawPcm {
+        sample_rate,
+        channels,
+    }))
+}
+
+// Read an uploaded audio file, negotiating on the Content-Type header:
+//  - application/octet-stream and audio/*: the body is the raw audio file
+//  - multipart/form-data: the "audio" field, or the first file field
+//  - anything else: the body is the base64 encoded audio file
+// The limit applies to the size of the decoded audio file.
+pub async fn read_audio(
+    req: &HttpRequest,
+    payload: web::Payload,
+    limit: usize,
+) -> Result<Vec<u8>, KakaiaError> {
+    let content_type = match req.headers().get(header::CONTENT_TYPE) {
+        Some(c) => c.to_str().unwrap_or("").to_lowercase(),
+        None => "".to_string(),
+    };
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+
+    if mime == "application/octet-stream" || mime.starts_with("audio/") {
+        read_chunks(payload, limit).await
+    } else if mime == "multipart/form-data" {
+        read_multipart(Multipart::new(req.headers(), payload), limit).await
+    } else {
+        // Base64 encoding inflates the upload by a third, plus some slack for
+        // padding and trailing newlines.
+        let base64_audio = read_chunks(payload, limit / 3 * 4 + 8).await?;
+        let audio_bytes = match base64::decode(trim_ascii(&base64_audio)) {
+            Ok(audio) => audio,
+            Err(e) => return Err(KakaiaError::Upload(format!("failed to decode base64 audio: {}", e))),
+        };
+        if audio_bytes.len() > limit {
+            return Err(KakaiaError::TooLarge(limit));
+        }
+        Ok(audio_bytes)
+    }
+}
+
+// Collect the request body or a multipart field, failing as soon as it
+// exceeds limit bytes.
+async fn read_chunks<S, E>(mut chunks: S, limit: usize) -> Result<Vec<u8>, KakaiaError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: fmt::Display,
+{
+    let mut bytes = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => return Err(KakaiaError::Upload(e.to_string())),
+        };
+        if bytes.len() + chunk.len() > limit {
+            return Err(KakaiaError::TooLarge(limit));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+// Find the audio file in a multipart form, failing if it exceeds limit bytes.
+async fn read_multipart(mut multipart: Multipart, limit: usize) -> Result<Vec<u8>, KakaiaError> {
+    while let Some(field) = multipart.next().await {
+        let field = match field {
+            Ok(f) => f,
+            Err(e) => return Err(KakaiaError::Upload(e.to_string())),
+        };
+        let is_audio = match field.content_disposition() {
+            Some(c) => c.get_name() == Some("audio") || c.get_filename().is_some(),
+            None => false,
+        };
+        if is_audio {
+            return read_chunks(field, limit).await;
+        }
+        // Skip over unrelated form fields.
+        read_chunks(field, limit).await?;
+    }
+    Err(KakaiaError::Upload("multipart upload has no audio field".to_string()))
+}
+
+fn trim_ascii(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |e| e + 1);
+    &bytes[start..end]
+}
@@ -0,0 +1,147 @@
+// Energy and zero-crossing based voice activity detection, used to trim
+// leading and trailing silence before speech recognition.
+
+// Analysis frames of 20 milliseconds at 16 kHz.
+const FRAME_LEN: usize = 320;
+// Frames below this energy (in dBFS) are always silence.
+const MIN_ENERGY: f64 = -55.0;
+// Quieter frames still count as speech if their zero-crossing rate is that of
+// unvoiced consonants such as "s" or "f".
+const FRICATIVE_ENERGY_MARGIN: f64 = 6.0;
+const FRICATIVE_ZCR: f64 = 0.3;
+// Clips without enough dynamic range to tell speech from noise are speech
+// throughout if they are at least this loud, and silence otherwise.
+const LOUD_ENERGY: f64 = -35.0;
+// Speech must last this many consecutive frames, ignoring clicks and pops.
+const MIN_SPEECH_FRAMES: usize = 3;
+// Silence kept around the detected speech, so word edges aren't clipped.
+const PADDING_FRAMES: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceActivity {
+    // Range of samples containing speech, including padding.
+    pub start: usize,
+    pub end: usize,
+}
+
+struct Frame {
+    energy: f64,
+    zcr: f64,
+}
+
+// Find the speech in 16 kHz mono samples, returning None if there is none. A
+// frame is speech if its energy is margin dB above the estimated noise floor.
+pub fn detect(samples: &[i16], margin: f64) -> Option<VoiceActivity> {
+    let frames: Vec<Frame> = samples.chunks(FRAME_LEN).map(analyze).collect();
+    if frames.is_empty() {
+        return None;
+    }
+
+    // Estimate the noise floor from the quietest tenth of the frames.
+    let mut energies: Vec<f64> = frames.iter().map(|f| f.energy).collect();
+    energies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let noise_floor = energies[energies.len() / 10];
+    let peak = energies[energies.len() - 1];
+    if peak - noise_floor < margin {
+        if peak > LOUD_ENERGY {
+            return Some(VoiceActivity {
+                start: 0,
+                end: samples.len(),
+            });
+        }
+        return None;
+    }
+    let threshold = (noise_floor + margin).max(MIN_ENERGY);
+
+    let is_speech: Vec<bool> = frames
+        .iter()
+        .map(|f| {
+            f.energy > threshold
+                || (f.energy > threshold - FRICATIVE_ENERGY_MARGIN
+                    && f.energy > MIN_ENERGY
+                    && f.zcr > FRICATIVE_ZCR)
+        })
+        .collect();
+
+    // Find the first and last runs of speech long enough to count.
+    let mut first = None;
+    let mut last = None;
+    let mut run = 0;
+    for (i, speech) in is_speech.iter().enumerate() {
+        if *speech {
+            run += 1;
+            if run >= MIN_SPEECH_FRAMES {
+                if first.is_none() {
+                    first = Some(i + 1 - run);
+                }
+                last = Some(i);
+            }
+        } else {
+            run = 0;
+        }
+    }
+
+    match (first, last) {
+        (Some(first), Some(last)) => {
+            let start = first.saturating_sub(PADDING_FRAMES) * FRAME_LEN;
+            let end = ((last + 1 + PADDING_FRAMES) * FRAME_LEN).min(samples.len());
+            Some(VoiceActivity { start, end })
+        }
+        _ => None,
+    }
+}
+
+fn analyze(frame: &[i16]) -> Frame {
+    let power: f64 = frame
+        .iter()
+        .map(|s| {
+            let s = *s as f64 / 32768.0;
+            s * s
+        })
+        .sum::<f64>()
+        / frame.len() as f64;
+    // Avoid -inf for digital silence.
+    let energy = 10.0 * (power + 1e-10).log10();
+
+    let crossings = frame
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0) != (pair[1] >= 0))
+        .count();
+    let zcr = crossings as f64 / frame.len() as f64;
+
+    Frame { energy, zcr }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARGIN: f64 = 12.0;
+
+    #[test]
+    fn silence_has_no_speech() {
+        assert!(detect(&[0; 48000], MARGIN).is_none());
+        assert!(detect(&[], MARGIN).is_none());
+    }
+
+    #[test]
+    fn tone_burst_trimmed_with_padding() {
+        // Half a second of 440 Hz from 1s into a 3s clip.
+        let mut samples = vec![0i16; 48000];
+        for (i, sample) in samples.iter_mut().enumerate().take(24000).skip(16000) {
+            *sample = (8000.0 * (2.0 * std::f64::consts::PI * 440.0 * i as f64 / 16000.0).sin()) as i16;
+        }
+        let activity = detect(&samples, MARGIN).unwrap();
+        assert_eq!(activity.start, 16000 - PADDING_FRAMES * FRAME_LEN);
+        assert_eq!(activity.end, 24000 + PADDING_FRAMES * FRAME_LEN);
+    }
+
+    #[test]
+    fn click_isnt_speech() {
+        let mut samples = vec![0i16; 48000];
+        for sample in samples.iter_mut().skip(16000).take(FRAME_LEN * (MIN_SPEECH_FRAMES - 1)) {
+            *sample = 8000;
+        }
+        assert!(detect(&samples, MARGIN).is_none());
+    }
+}
@@ -0,0 +1,387 @@
+// Minimal WebM (Matroska) demuxer, extracting the Opus packets recorded by
+// browsers with MediaRecorder.
+
+const EBML_HEADER: u32 = 0x1A45_DFA3;
+const SEGMENT: u32 = 0x1853_8067;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const AUDIO: u32 = 0xE1;
+const CHANNELS: u32 = 0x9F;
+const CLUSTER: u32 = 0x1F43_B675;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+// Master elements whose children are scanned inline. This also copes with the
+// unknown sizes MediaRecorder writes for live Segments and Clusters.
+const CONTAINERS: [u32; 6] = [SEGMENT, TRACKS, TRACK_ENTRY, AUDIO, CLUSTER, BLOCK_GROUP];
+
+pub struct OpusTrack {
+    pub channels: u32,
+    // Samples (at 48 kHz) to discard from the start of the decoded audio.
+    pub pre_skip: usize,
+    pub packets: Vec<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Track {
+    number: u64,
+    codec_id: String,
+    codec_private: Vec<u8>,
+    channels: u32,
+}
+
+// Find the first Opus track and return its packets.
+pub fn demux_opus(data: &[u8]) -> Result<OpusTrack, String> {
+    let mut tracks: Vec<Track> = Vec::new();
+    let mut frames: Vec<(u64, &[u8])> = Vec::new();
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (id, id_len) = match read_vint(&data[pos..], false) {
+            Some((Some(id), len)) => (id, len),
+            _ => return Err(format!("invalid WebM element id at byte {}", pos)),
+        };
+        let (size, size_len) = match read_vint(&data[pos + id_len..], true) {
+            Some(v) => v,
+            None => return Err(format!("invalid WebM element size at byte {}", pos + id_len)),
+        };
+        let id = id as u32;
+        let body = pos + id_len + size_len;
+
+        if CONTAINERS.contains(&id) {
+            if id == TRACK_ENTRY {
+                tracks.push(Track::default());
+            }
+            pos = body;
+            continue;
+        }
+        let size = match size {
+            Some(s) => s as usize,
+            None => return Err(format!("WebM element {:X} has an unknown size", id)),
+        };
+        if size > data.len() - body {
+            return Err(format!("WebM element {:X} at byte {} is truncated", id, pos));
+        }
+        let end = body + size;
+        let value = &data[body..end];
+
+        match id {
+            EBML_HEADER => (),
+            TRACK_NUMBER | CODEC_ID | CODEC_PRIVATE | CHANNELS => {
+                let track = match tracks.last_mut() {
+                    Some(t) => t,
+                    None => return Err("WebM track element outside of a track".to_string()),
+                };
+                match id {
+                    TRACK_NUMBER => track.number = read_uint(value),
+                    CODEC_ID => track.codec_id = String::from_utf8_lossy(value).to_string(),
+                    CODEC_PRIVATE => track.codec_private = value.to_vec(),
+                    _ => track.channels = read_uint(value) as u32,
+                }
+            }
+            BLOCK | SIMPLE_BLOCK => {
+                let (track, track_len) = match read_vint(value, true) {
+                    Some((Some(t), len)) => (t, len),
+                    _ => return Err("invalid WebM block".to_string()),
+                };
+                // Skip the timecode (2 bytes) to the flags.
+                if value.len() < track_len + 3 {
+                    return Err("truncated WebM block".to_string());
+                }
+                let flags = value[track_len + 2];
+                for frame in unlace(&value[track_len + 3..], flags)? {
+                    frames.push((track, frame));
+                }
+            }
+            _ => (),
+        }
+        pos = end;
+    }
+
+    let track = match tracks.iter().find(|t| t.codec_id == "A_OPUS") {
+        Some(t) => t,
+        None => return Err("WebM file has no Opus audio track".to_string()),
+    };
+    // The codec private data is an OpusHead header, which has the pre-skip.
+    let pre_skip = if track.codec_private.len() >= 12 && track.codec_private.starts_with(b"OpusHead") {
+        u16::from_le_bytes([track.codec_private[10], track.codec_private[11]]) as usize
+    } else {
+        0
+    };
+    let channels = if track.channels > 0 {
+        track.channels
+    } else if track.codec_private.len() > 9 {
+        track.codec_private[9] as u32
+    } else {
+        1
+    };
+
+    Ok(OpusTrack {
+        channels,
+        pre_skip,
+        packets: frames
+            .into_iter()
+            .filter(|(t, _)| *t == track.number)
+            .map(|(_, f)| f.to_vec())
+            .collect(),
+    })
+}
+
+// Split the frames of a block according to its lacing.
+fn unlace(data: &[u8], flags: u8) -> Result<Vec<&[u8]>, String> {
+    let lacing = (flags >> 1) & 0x03;
+    if lacing == 0 {
+        return Ok(vec![data]);
+    }
+    if data.is_empty() {
+        return Err("truncated WebM lacing".to_string());
+    }
+    let count = data[0] as usize + 1;
+    let mut pos = 1;
+    let mut sizes: Vec<usize> = Vec::with_capacity(count);
+    match lacing {
+        // Xiph lacing: each size is a run of 255s plus a final byte.
+        1 => {
+            for _ in 0..count - 1 {
+                let mut size = 0;
+                loop {
+                    let byte = match data.get(pos) {
+                        Some(b) => *b,
+                        None => return Err("truncated WebM lacing".to_string()),
+                    };
+                    pos += 1;
+                    size += byte as usize;
+                    if byte != 255 {
+                        break;
+                    }
+                }
+                sizes.push(size);
+            }
+        }
+        // Fixed lacing: all frames are the same size.
+        2 => {
+            let size = (data.len() - pos) / count;
+            sizes = vec![size; count - 1];
+        }
+        // EBML lacing: the first size, then signed differences.
+        _ if count > 1 => {
+            let (first, len) = match read_vint(&data[pos..], true) {
+                Some((Some(s), l)) => (s as i64, l),
+                _ => return Err("invalid WebM lacing".to_string()),
+            };
+            pos += len;
+            sizes.push(first as usize);
+            let mut size = first;
+            for _ in 1..count - 1 {
+                let (raw, len) = match read_vint(&data[pos..], false) {
+                    Some((Some(r), l)) => (r, l),
+                    _ => return Err("invalid WebM lacing".to_string()),
+                };
+                pos += len;
+                // Strip the length marker, and remove the bias to sign it.
+                let bits = 7 * len as u32;
+                let value = (raw & ((1u64 << bits) - 1)) as i64 - ((1i64 << (bits - 1)) - 1);
+                size += value;
+                if size < 0 {
+                    return Err("invalid WebM lacing".to_string());
+                }
+                sizes.push(size as usize);
+            }
+        }
+        _ => (),
+    }
+
+    let mut frames = Vec::with_capacity(count);
+    for size in sizes {
+        if pos + size > data.len() {
+            return Err("truncated WebM lacing".to_string());
+        }
+        frames.push(&data[pos..pos + size]);
+        pos += size;
+    }
+    // The last frame is whatever remains.
+    frames.push(&data[pos..]);
+    Ok(frames)
+}
+
+// Read an EBML variable length integer, returning the value and its length.
+// Ids keep their length marker; sizes drop it, with all ones meaning unknown.
+fn read_vint(data: &[u8], size: bool) -> Option<(Option<u64>, usize)> {
+    let first = *data.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 || data.len() < len {
+        return None;
+    }
+    let mut value = if size {
+        (first as u64) & (0xFFu64 >> len)
+    } else {
+        first as u64
+    };
+    for byte in &data[1..len] {
+        value = (value << 8) | *byte as u64;
+    }
+    if size && value == (1u64 << (7 * len)) - 1 {
+        return Some((None, len));
+    }
+    Some((Some(value), len))
+}
+
+fn read_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An element with a one byte size.
+    fn element(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.push(0x80 | body.len() as u8);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    // Master elements of unknown size, as MediaRecorder writes them.
+    fn unknown_size(id: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        bytes
+    }
+
+    fn simple_block(track: u8, frame: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x80 | track, 0x00, 0x00, 0x80];
+        body.extend_from_slice(frame);
+        element(&[0xA3], &body)
+    }
+
+    // A stereo Opus track with a pre-skip of 312, and a block from another track.
+    fn recording() -> Vec<u8> {
+        let mut opus_head = b"OpusHead\x01\x02".to_vec();
+        opus_head.extend_from_slice(&[0x38, 0x01, 0x80, 0xBB, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let mut entry = element(&[0xD7], &[0x01]);
+        entry.extend(element(&[0x86], b"A_OPUS"));
+        entry.extend(element(&[0x63, 0xA2], &opus_head));
+
+        let mut data = element(&[0x1A, 0x45, 0xDF, 0xA3], &element(&[0x42, 0x82], b"webm"));
+        data.extend(unknown_size(&[0x18, 0x53, 0x80, 0x67]));
+        data.extend(element(&[0x16, 0x54, 0xAE, 0x6B], &element(&[0xAE], &entry)));
+        data.extend(unknown_size(&[0x1F, 0x43, 0xB6, 0x75]));
+        data.extend(simple_block(1, &[1, 2, 3]));
+        data.extend(simple_block(2, &[9]));
+        data.extend(simple_block(1, &[4]));
+        data
+    }
+
+    #[test]
+    fn vint_widths() {
+        assert_eq!(read_vint(&[0x81], true), Some((Some(1), 1)));
+        assert_eq!(read_vint(&[0x40, 0x02, 0xFF], true), Some((Some(2), 2)));
+        assert_eq!(read_vint(&[0x21, 0x00, 0x00], true), Some((Some(0x10000), 3)));
+        assert_eq!(read_vint(&[0x01, 0, 0, 0, 0, 0, 0, 0x05], true), Some((Some(5), 8)));
+        // Ids keep their length marker.
+        assert_eq!(read_vint(&[0x1A, 0x45, 0xDF, 0xA3], false), Some((Some(0x1A45_DFA3), 4)));
+    }
+
+    #[test]
+    fn vint_unknown_size() {
+        assert_eq!(read_vint(&[0xFF], true), Some((None, 1)));
+        assert_eq!(read_vint(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], true), Some((None, 8)));
+        assert_eq!(read_vint(&[0xFF], false), Some((Some(0xFF), 1)));
+    }
+
+    #[test]
+    fn vint_invalid() {
+        // A zero first byte would mean a vint longer than 8 bytes.
+        assert_eq!(read_vint(&[0x00, 0x81, 0, 0, 0, 0, 0, 0, 0], true), None);
+        assert_eq!(read_vint(&[0x40], true), None);
+        assert_eq!(read_vint(&[], true), None);
+    }
+
+    #[test]
+    fn no_lacing() {
+        assert_eq!(unlace(&[1, 2, 3], 0x80).unwrap(), vec![&[1, 2, 3][..]]);
+    }
+
+    #[test]
+    fn xiph_lacing() {
+        let frames = unlace(&[2, 2, 1, 7, 7, 8, 9, 9, 9], 0x02).unwrap();
+        assert_eq!(frames, vec![&[7, 7][..], &[8][..], &[9, 9, 9][..]]);
+
+        // Sizes of 255 and over continue into the next byte.
+        let mut data = vec![1, 255, 1];
+        data.extend_from_slice(&[7; 256]);
+        data.push(8);
+        let frames = unlace(&data, 0x02).unwrap();
+        assert_eq!(frames, vec![&[7; 256][..], &[8][..]]);
+    }
+
+    #[test]
+    fn fixed_lacing() {
+        let frames = unlace(&[2, 1, 1, 2, 2, 3, 3], 0x04).unwrap();
+        assert_eq!(frames, vec![&[1, 1][..], &[2, 2][..], &[3, 3][..]]);
+    }
+
+    #[test]
+    fn ebml_lacing() {
+        // Sizes 2, then 2 + 1 and 3 - 1, and the rest.
+        let frames = unlace(&[3, 0x82, 0xC0, 0xBE, 7, 7, 8, 8, 8, 9, 9, 6], 0x06).unwrap();
+        assert_eq!(frames, vec![&[7, 7][..], &[8, 8, 8][..], &[9, 9][..], &[6][..]]);
+    }
+
+    #[test]
+    fn truncated_lacing() {
+        assert!(unlace(&[], 0x02).is_err());
+        assert!(unlace(&[2, 255], 0x02).is_err());
+        assert!(unlace(&[1, 5, 7, 7], 0x02).is_err());
+        assert!(unlace(&[1, 0x85, 7, 7], 0x06).is_err());
+        assert!(unlace(&[1, 0x00, 7, 7], 0x06).is_err());
+        // A negative size.
+        assert!(unlace(&[2, 0x81, 0x80, 7, 7], 0x06).is_err());
+    }
+
+    #[test]
+    fn demux_opus_track() {
+        let track = demux_opus(&recording()).unwrap();
+        assert_eq!(track.channels, 2);
+        assert_eq!(track.pre_skip, 312);
+        assert_eq!(track.packets, vec![vec![1, 2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn demux_truncated() {
+        let data = recording();
+        // Cut inside the last block.
+        assert!(demux_opus(&data[..data.len() - 1]).is_err());
+        // Cut inside the size of the last block.
+        let mut data = recording();
+        data.extend_from_slice(&[0xA3, 0x40]);
+        assert!(demux_opus(&data).is_err());
+        // Cut inside an id.
+        let mut data = recording();
+        data.extend_from_slice(&[0x1F, 0x43]);
+        assert!(demux_opus(&data).is_err());
+    }
+
+    #[test]
+    fn demux_oversized() {
+        let mut data = recording();
+        data.extend_from_slice(&[0xA3, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE, 0x81]);
+        assert!(demux_opus(&data).is_err());
+        // An id of more than 8 bytes.
+        let mut data = recording();
+        data.extend_from_slice(&[0x00, 0x81]);
+        assert!(demux_opus(&data).is_err());
+    }
+
+    #[test]
+    fn demux_without_opus() {
+        let mut data = element(&[0x1A, 0x45, 0xDF, 0xA3], &[]);
+        data.extend(element(&[0xAE], &element(&[0x86], b"A_VORBIS")));
+        assert!(demux_opus(&data).is_err());
+    }
+}